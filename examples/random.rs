@@ -1,5 +1,9 @@
-use image::{imageops::overlay, io::Reader, GenericImageView};
-use image_go_nord::{convert, Options, NORD};
+use image::io::Reader;
+use image_go_nord::{
+    convert,
+    region::{convert_region, Region},
+    Options, NORD,
+};
 use std::{
     error::Error,
     io::{Cursor, Read},
@@ -8,7 +12,7 @@ use std::{
 
 fn main() -> Result<(), Box<dyn Error>> {
     let start = Instant::now();
-    let mut pic = {
+    let pic = {
         let req = ureq::get("https://source.unsplash.com/random").call()?;
         let mut bytes = if let Some(len) = req.header("Content-Length").and_then(|s| s.parse().ok())
         {
@@ -28,22 +32,27 @@ fn main() -> Result<(), Box<dyn Error>> {
     );
 
     let (w, h) = pic.dimensions();
-    let right_half = pic.view(w / 2, 0, w / 2, h).to_image();
+    let right_half = Region {
+        x: w / 2,
+        y: 0,
+        width: w - w / 2,
+        height: h,
+    };
 
     let start = Instant::now();
-    let converted = convert(
-        &right_half,
-        Options {
-            quantize: 5,
-            blur: 0.2,
-            ..Default::default()
-        },
-        &NORD,
-    );
+    let pic = convert_region(&pic, right_half, 0., |img| {
+        convert(
+            img,
+            Options {
+                quantize: 5,
+                blur: 0.2,
+                ..Default::default()
+            },
+            &NORD,
+        )
+    });
     eprintln!("Converted to nord in {:.3}s", start.elapsed().as_secs_f32());
 
-    overlay(&mut pic, &converted, w / 2, 0);
-
     pic.save("nord.jpg")?;
     eprintln!("Saved to 'nord.jpg'");
 
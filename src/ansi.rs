@@ -0,0 +1,305 @@
+use crate::{
+    palette::palettes::TERMINAL,
+    utils::{delta, luminance},
+};
+use image::{imageops::ColorMap, Rgba, RgbaImage};
+use std::str::FromStr;
+
+/// Characters used by [`Characters::Luminance`], from empty to full
+const RAMP: &[char] = &[' ', '.', ':', '-', '=', '+', '*', '#', '%', '@'];
+/// Quadrant block characters indexed by a bit mask of the filled quadrants:
+/// 1 is top left, 2 is top right, 4 is bottom left, and 8 is bottom right
+const QUADRANTS: [char; 16] = [
+    ' ', '▘', '▝', '▀', '▖', '▌', '▞', '▛', '▗', '▚', '▐', '▜', '▄', '▙', '▟', '█',
+];
+/// Pixels with an alpha below this are left out of their cell
+const ALPHA_THRESHOLD: u8 = 128;
+
+/// How the colors of ANSI art are written.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Colors {
+    /// Write palette colors as 24-bit colors.
+    #[default]
+    TrueColor,
+    /// Restrict colors to the 16 terminal color slots of the Nord terminal
+    /// themes (see [`TERMINAL`](crate::palette::palettes::TERMINAL)), so the
+    /// art follows the terminal's own color scheme.
+    Terminal,
+}
+
+impl FromStr for Colors {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "truecolor" | "24bit" => Ok(Colors::TrueColor),
+            "terminal" | "16" => Ok(Colors::Terminal),
+            _ => Err(format!(
+                "unknown colors '{}', expected one of: truecolor, terminal",
+                s
+            )),
+        }
+    }
+}
+
+/// How the character of each cell of ANSI art is chosen.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Characters {
+    /// Pick a character from ` .:-=+*#%@` by how much of the cell is covered
+    /// by its lighter color.
+    #[default]
+    Luminance,
+    /// Pick the quadrant block character (like `▀` or `▚`) that best matches
+    /// the shape of the lighter color in the cell.
+    Shape,
+}
+
+impl FromStr for Characters {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "luminance" => Ok(Characters::Luminance),
+            "shape" => Ok(Characters::Shape),
+            _ => Err(format!(
+                "unknown characters '{}', expected one of: luminance, shape",
+                s
+            )),
+        }
+    }
+}
+
+/// These options control how `ansi_art` renders an image as text.
+///
+/// Like [`Options`](crate::Options), `AnsiOptions` implements
+/// [`std::default::Default`], so you can override only the fields you need.
+///
+/// ```
+/// # use image_go_nord::ansi::{AnsiOptions, Characters};
+/// let options = AnsiOptions {
+///     columns: 120,
+///     characters: Characters::Shape,
+///     ..Default::default()
+/// };
+/// ```
+#[cfg_attr(feature = "serde", derive(serde::Deserialize))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AnsiOptions {
+    /// Width of the art in characters. Images are never scaled up, so narrow
+    /// images use one character per pixel instead. Each character covers an
+    /// area twice as tall as it is wide.
+    pub columns: u32,
+    /// How colors are written.
+    pub colors: Colors,
+    /// How characters are chosen.
+    pub characters: Characters,
+}
+
+impl Default for AnsiOptions {
+    fn default() -> Self {
+        AnsiOptions {
+            columns: 80,
+            colors: Colors::default(),
+            characters: Characters::default(),
+        }
+    }
+}
+
+/// Render an image as ANSI art, using colors from a palette.
+///
+/// The image is divided into cells, and each cell is drawn as one character
+/// with a foreground and background color: the two palette colors that are
+/// most common in the cell, with the lighter one in the foreground. Cells
+/// without opaque pixels are left blank. Every line ends with a reset, so the
+/// result can be printed or saved as an `.ans` file.
+///
+/// ```
+/// # use image::{Rgba, RgbaImage};
+/// # use image_go_nord::{ansi::{ansi_art, AnsiOptions, Colors}, NORD};
+/// let img = RgbaImage::from_pixel(4, 8, Rgba([0x88, 0xC0, 0xD0, 0xFF]));
+///
+/// let art = ansi_art(&img, &NORD, Default::default());
+/// assert_eq!(art.lines().count(), 4);
+/// assert!(art.contains("\x1b[48;2;136;192;208m"));
+///
+/// let options = AnsiOptions { colors: Colors::Terminal, ..Default::default() };
+/// let art = ansi_art(&img, &NORD, options);
+/// assert!(art.contains("\x1b[46m"));
+/// ```
+///
+/// # Panics
+///
+/// The palette must support [`ColorMap::lookup`].
+pub fn ansi_art(
+    img: &RgbaImage,
+    palette: &impl ColorMap<Color = Rgba<u8>>,
+    opt: AnsiOptions,
+) -> String {
+    match opt.colors {
+        Colors::TrueColor => render(img, palette, opt, |_, Rgba([r, g, b, _]), fg| {
+            format!("\x1b[{};2;{};{};{}m", if fg { 38 } else { 48 }, r, g, b)
+        }),
+        Colors::Terminal => render(img, &TERMINAL, opt, |i, _, fg| {
+            // the first 8 slots use codes 30-37 and 40-47, and the bright ones
+            // use 90-97 and 100-107
+            let base = match (fg, i < 8) {
+                (true, true) => 30,
+                (true, false) => 90,
+                (false, true) => 40,
+                (false, false) => 100,
+            };
+            format!("\x1b[{}m", base + i % 8)
+        }),
+    }
+}
+
+/// Render ANSI art, using `sgr` to write the escape sequence that sets a
+/// palette entry as the foreground (`true`) or background (`false`) color
+fn render(
+    img: &RgbaImage,
+    palette: &impl ColorMap<Color = Rgba<u8>>,
+    opt: AnsiOptions,
+    sgr: impl Fn(usize, Rgba<u8>, bool) -> String,
+) -> String {
+    assert!(palette.has_lookup(), "palettes must support lookup");
+    let colors: Vec<_> = (0..).map_while(|i| palette.lookup(i)).collect();
+    let (w, h) = img.dimensions();
+    let mut out = String::new();
+    if w == 0 || h == 0 {
+        return out;
+    }
+
+    let columns = opt.columns.clamp(1, w);
+    let cell_w = w as f64 / columns as f64;
+    let cell_h = cell_w * 2.;
+    let rows = (h as f64 / cell_h).ceil() as u32;
+    // the start and end of a cell along one axis, at least one pixel long
+    let span = |i: u32, size: f64, max: u32| {
+        let start = ((i as f64 * size) as u32).min(max - 1);
+        let end = (((i + 1) as f64 * size) as u32).clamp(start + 1, max);
+        (start, end)
+    };
+
+    for row in 0..rows {
+        let (y0, y1) = span(row, cell_h, h);
+        let mut current = None;
+        for column in 0..columns {
+            let (x0, x1) = span(column, cell_w, w);
+            let pixels = || {
+                (y0..y1)
+                    .flat_map(move |y| (x0..x1).map(move |x| (x, y)))
+                    .map(|(x, y)| (x, y, img.get_pixel(x, y)))
+                    .filter(|(_, _, p)| p[3] >= ALPHA_THRESHOLD)
+            };
+
+            // find the two most common palette entries in the cell
+            let mut counts = vec![0u32; colors.len()];
+            pixels().for_each(|(_, _, p)| counts[palette.index_of(p)] += 1);
+            let mut ranked: Vec<_> = (0..colors.len()).filter(|&i| counts[i] > 0).collect();
+            ranked.sort_by_key(|&i| std::cmp::Reverse(counts[i]));
+            let (fg, bg) = match ranked[..] {
+                [] => {
+                    // nothing to draw, so leave the cell blank
+                    if current.take().is_some() {
+                        out.push_str("\x1b[0m");
+                    }
+                    out.push(' ');
+                    continue;
+                }
+                [only] => (only, only),
+                [a, b, ..] if luminance(&colors[a].0) >= luminance(&colors[b].0) => (a, b),
+                [a, b, ..] => (b, a),
+            };
+
+            // measure how much of each quadrant is covered by the foreground
+            let mut covered = [0u32; 4];
+            let mut total = [0u32; 4];
+            for (x, y, p) in pixels() {
+                let quadrant = ((x - x0) * 2 / (x1 - x0) + (y - y0) * 2 / (y1 - y0) * 2) as usize;
+                total[quadrant] += 1;
+                if delta(&p.0[..3], &colors[fg].0[..3]) <= delta(&p.0[..3], &colors[bg].0[..3]) {
+                    covered[quadrant] += 1;
+                }
+            }
+            let character = if fg == bg {
+                ' '
+            } else {
+                match opt.characters {
+                    Characters::Luminance => {
+                        let coverage =
+                            covered.iter().sum::<u32>() as f64 / total.iter().sum::<u32>() as f64;
+                        RAMP[(coverage * (RAMP.len() - 1) as f64).round() as usize]
+                    }
+                    Characters::Shape => {
+                        let coverage = |q: usize| {
+                            if total[q] == 0 {
+                                0.
+                            } else {
+                                covered[q] as f64 / total[q] as f64
+                            }
+                        };
+                        let error = |mask: usize| {
+                            (0..4)
+                                .map(|q| (coverage(q) - (mask >> q & 1) as f64).powi(2))
+                                .sum::<f64>()
+                        };
+                        let mask = (0..QUADRANTS.len())
+                            .min_by(|&a, &b| error(a).partial_cmp(&error(b)).unwrap())
+                            .unwrap();
+                        QUADRANTS[mask]
+                    }
+                }
+            };
+
+            // only change colors when they differ from the previous cell
+            if current != Some((fg, bg)) {
+                out.push_str(&sgr(fg, colors[fg], true));
+                out.push_str(&sgr(bg, colors[bg], false));
+                current = Some((fg, bg));
+            }
+            out.push(character);
+        }
+        out.push_str("\x1b[0m\n");
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::NORD;
+
+    #[test]
+    fn test_shape() {
+        // left half nord6, right half nord0
+        let img = RgbaImage::from_fn(8, 16, |x, _| {
+            if x < 4 {
+                Rgba([0xEC, 0xEF, 0xF4, 0xFF])
+            } else {
+                Rgba([0x2E, 0x34, 0x40, 0xFF])
+            }
+        });
+        let options = AnsiOptions {
+            columns: 1,
+            characters: Characters::Shape,
+            ..Default::default()
+        };
+        assert_eq!(
+            ansi_art(&img, &NORD, options),
+            "\x1b[38;2;236;239;244m\x1b[48;2;46;52;64m▌\x1b[0m\n"
+        );
+    }
+
+    #[test]
+    fn test_transparent() {
+        let img = RgbaImage::new(2, 4);
+        assert_eq!(
+            ansi_art(&img, &NORD, Default::default()),
+            "  \x1b[0m\n".repeat(2)
+        );
+    }
+}
@@ -0,0 +1,366 @@
+use crate::{
+    palette::Entry,
+    utils::{delta, lab_to_rgb, rgb_to_lab},
+};
+use image::{imageops::ColorMap, Pixel, Rgb, Rgba, RgbaImage};
+use itertools::Itertools;
+
+/// Maximum number of refinement passes when clustering an image's colors
+const MAX_ITERATIONS: usize = 32;
+
+/// A color map that balances palette usage by assigning the dominant colors of
+/// an image to palette entries with minimum-cost matching.
+///
+/// Nearest-color matching often maps most of an image onto one or two palette
+/// colors (for example, everything dark becomes `nord0`). An `Assignment`
+/// instead clusters the colors of the image, then finds the assignment of
+/// clusters to palette entries that minimizes the total perceptual distance
+/// (weighted by how many pixels are in each cluster) while using every
+/// palette entry as evenly as possible. Pixels are then mapped to the entry
+/// assigned to their cluster.
+///
+/// When there are more clusters than palette entries, each entry is shared by
+/// at most `ceil(clusters / entries)` clusters.
+///
+/// ```
+/// # use image::{imageops::ColorMap, Rgba, RgbaImage};
+/// # use image_go_nord::{assignment::Assignment, palette::StaticPalette};
+/// // two dark colors
+/// let mut img = RgbaImage::from_pixel(2, 1, Rgba([10, 10, 10, 255]));
+/// img.put_pixel(1, 0, Rgba([60, 60, 60, 255]));
+///
+/// // nearest-color matching would map both to black
+/// let palette = StaticPalette::from_rgb_hex([0x000000, 0xFFFFFF]);
+/// let assignment = Assignment::new(&img, &palette, 2);
+/// assert_eq!(assignment.index_of(&Rgba([10, 10, 10, 255])), 0);
+/// assert_eq!(assignment.index_of(&Rgba([60, 60, 60, 255])), 1);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Assignment {
+    palette: Vec<Entry>,
+    clusters: Vec<Cluster>,
+}
+
+/// One row of the mapping table of an [`Assignment`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Mapping {
+    /// Average color of the cluster
+    pub cluster: Rgba<u8>,
+    /// Fraction of the image's pixels in this cluster, between 0 and 1
+    pub weight: f32,
+    /// Index of the palette entry the cluster is mapped to
+    pub index: usize,
+    /// Palette color the cluster is mapped to
+    pub color: Rgba<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Cluster {
+    color: Entry,
+    weight: f32,
+    index: usize,
+}
+
+impl Assignment {
+    /// Cluster the colors of `img` into (at most) the given number of
+    /// clusters and assign them to the entries of `palette`. Passing the
+    /// number of palette entries maps each cluster to a distinct entry.
+    ///
+    /// # Panics
+    ///
+    /// The palette must support [`ColorMap::lookup`] and have at least one
+    /// entry, and `clusters` must not be zero.
+    pub fn new(
+        img: &RgbaImage,
+        palette: &impl ColorMap<Color = Rgba<u8>>,
+        clusters: usize,
+    ) -> Self {
+        assert!(palette.has_lookup(), "palettes must support lookup");
+        assert!(clusters > 0, "there must be at least one cluster");
+        let palette: Vec<Entry> = (0..)
+            .map_while(|i| palette.lookup(i))
+            .map(|c| c.to_rgb().0)
+            .collect();
+        assert!(!palette.is_empty(), "palettes must not be empty");
+
+        let (centers, weights) = cluster(img, clusters);
+
+        // give each palette entry enough slots to take every cluster
+        let slots = centers.len().div_ceil(palette.len());
+        let palette_lab: Vec<_> = palette.iter().map(|c| rgb_to_lab(c)).collect();
+        let costs: Vec<Vec<f64>> = centers
+            .iter()
+            .zip(&weights)
+            .map(|(center, &weight)| {
+                let center = rgb_to_lab(center);
+                palette_lab
+                    .iter()
+                    .flat_map(|entry| std::iter::repeat_n(entry, slots))
+                    .map(|entry| weight as f64 * lab_distance(&center, entry) as f64)
+                    .collect()
+            })
+            .collect();
+
+        let clusters = hungarian(&costs)
+            .into_iter()
+            .zip(centers.into_iter().zip(weights))
+            .map(|(slot, (color, weight))| Cluster {
+                color,
+                weight,
+                index: slot / slots,
+            })
+            .collect();
+        Assignment { palette, clusters }
+    }
+
+    /// The mapping table, with one row for each cluster, from the heaviest
+    /// cluster to the lightest.
+    pub fn mapping(&self) -> Vec<Mapping> {
+        self.clusters
+            .iter()
+            .map(|c| Mapping {
+                cluster: Rgb(c.color).to_rgba(),
+                weight: c.weight,
+                index: c.index,
+                color: Rgb(self.palette[c.index]).to_rgba(),
+            })
+            .sorted_by(|a, b| b.weight.partial_cmp(&a.weight).unwrap())
+            .collect()
+    }
+}
+
+impl ColorMap for Assignment {
+    type Color = Rgba<u8>;
+
+    fn index_of(&self, color: &Self::Color) -> usize {
+        // find the closest cluster, then the entry assigned to it
+        self.clusters
+            .iter()
+            .min_by_key(|c| delta(color.channels(), &c.color))
+            .unwrap()
+            .index
+    }
+
+    fn map_color(&self, color: &mut Self::Color) {
+        let c = self.palette[self.index_of(color)];
+        // don't overwrite the alpha channel
+        color.channels_mut()[..3].copy_from_slice(&c)
+    }
+
+    fn has_lookup(&self) -> bool {
+        true
+    }
+
+    fn lookup(&self, index: usize) -> Option<Self::Color> {
+        self.palette.get(index).map(|&entry| Rgb(entry).to_rgba())
+    }
+}
+
+fn lab_distance(a: &[f32; 3], b: &[f32; 3]) -> f32 {
+    a.iter()
+        .zip(b)
+        .map(|(a, b)| (a - b).powi(2))
+        .sum::<f32>()
+        .sqrt()
+}
+
+/// Find the dominant colors of an image using weighted k-means on a 15-bit
+/// color histogram. Returns the cluster centers and the fraction of pixels in
+/// each one. Empty clusters are dropped.
+fn cluster(img: &RgbaImage, k: usize) -> (Vec<Entry>, Vec<f32>) {
+    // histogram of colors with 5 bits per channel, keeping the sum of the
+    // colors in each bin so the bins can be represented by their average
+    let mut bins = vec![(0u32, [0u64; 3]); 1 << 15];
+    for p in img.pixels() {
+        let [r, g, b, _] = p.0;
+        let i = (r as usize >> 3) << 10 | (g as usize >> 3) << 5 | b as usize >> 3;
+        bins[i].0 += 1;
+        bins[i]
+            .1
+            .iter_mut()
+            .zip(&[r, g, b])
+            .for_each(|(s, &c)| *s += c as u64);
+    }
+    let bins: Vec<([f32; 3], f32)> = bins
+        .into_iter()
+        .filter(|(n, _)| *n > 0)
+        .map(|(n, sum)| (rgb_to_lab(&sum.map(|s| (s / n as u64) as u8)), n as f32))
+        .collect();
+    if bins.is_empty() {
+        return (Vec::new(), Vec::new());
+    }
+
+    // deterministic farthest-point initialization, starting from the most
+    // common color
+    let mut centers = vec![
+        bins.iter()
+            .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
+            .unwrap()
+            .0,
+    ];
+    while centers.len() < k.min(bins.len()) {
+        let (next, _) = bins
+            .iter()
+            .map(|(color, _)| {
+                let d = centers
+                    .iter()
+                    .map(|c| lab_distance(c, color))
+                    .fold(f32::INFINITY, f32::min);
+                (color, d)
+            })
+            .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
+            .unwrap();
+        centers.push(*next);
+    }
+
+    let nearest = |centers: &[[f32; 3]], color: &[f32; 3]| {
+        centers
+            .iter()
+            .map(|c| lab_distance(c, color))
+            .position_min_by(|a, b| a.partial_cmp(b).unwrap())
+            .unwrap()
+    };
+    let mut sums = vec![([0f32; 3], 0f32); centers.len()];
+    for _ in 0..MAX_ITERATIONS {
+        sums.iter_mut().for_each(|s| *s = ([0.; 3], 0.));
+        for (color, n) in &bins {
+            let s = &mut sums[nearest(&centers, color)];
+            s.0.iter_mut().zip(color).for_each(|(s, c)| *s += c * n);
+            s.1 += n;
+        }
+        let updated: Vec<_> = sums
+            .iter()
+            .zip(&centers)
+            .map(|((sum, n), &c)| if *n > 0. { sum.map(|s| s / n) } else { c })
+            .collect();
+        if updated == centers {
+            break;
+        }
+        centers = updated;
+    }
+
+    let total = img.pixels().len() as f32;
+    centers
+        .into_iter()
+        .zip(sums)
+        .filter(|(_, (_, n))| *n > 0.)
+        .map(|(c, (_, n))| (lab_to_rgb(c), n / total))
+        .unzip()
+}
+
+/// Solve the rectangular assignment problem for a cost matrix with at most as
+/// many rows as columns using the Hungarian algorithm. Returns the column
+/// assigned to each row.
+fn hungarian(costs: &[Vec<f64>]) -> Vec<usize> {
+    let n = costs.len();
+    let m = costs.first().map_or(0, Vec::len);
+    debug_assert!(n <= m, "more rows than columns");
+
+    // potentials and matching use 1-based indices, with 0 as a sentinel
+    let mut u = vec![0.; n + 1];
+    let mut v = vec![0.; m + 1];
+    let mut row_of = vec![0; m + 1];
+    let mut way = vec![0; m + 1];
+    for i in 1..=n {
+        row_of[0] = i;
+        let mut j0 = 0;
+        let mut min_v = vec![f64::INFINITY; m + 1];
+        let mut used = vec![false; m + 1];
+        loop {
+            used[j0] = true;
+            let i0 = row_of[j0];
+            let mut d = f64::INFINITY;
+            let mut j1 = 0;
+            for j in 1..=m {
+                if !used[j] {
+                    let cur = costs[i0 - 1][j - 1] - u[i0] - v[j];
+                    if cur < min_v[j] {
+                        min_v[j] = cur;
+                        way[j] = j0;
+                    }
+                    if min_v[j] < d {
+                        d = min_v[j];
+                        j1 = j;
+                    }
+                }
+            }
+            for j in 0..=m {
+                if used[j] {
+                    u[row_of[j]] += d;
+                    v[j] -= d;
+                } else {
+                    min_v[j] -= d;
+                }
+            }
+            j0 = j1;
+            if row_of[j0] == 0 {
+                break;
+            }
+        }
+        // walk back along the augmenting path
+        loop {
+            let j1 = way[j0];
+            row_of[j0] = row_of[j1];
+            j0 = j1;
+            if j0 == 0 {
+                break;
+            }
+        }
+    }
+
+    let mut assignment = vec![0; n];
+    for j in 1..=m {
+        if row_of[j] > 0 {
+            assignment[row_of[j] - 1] = j - 1;
+        }
+    }
+    assignment
+}
+
+#[cfg(test)]
+mod tests {
+    use super::hungarian;
+    use crate::{convert, Mode, Options};
+    use image::{imageops::ColorMap, Rgba, RgbaImage};
+
+    /// A color map that can't list its colors
+    struct BlackAndWhite;
+
+    impl ColorMap for BlackAndWhite {
+        type Color = Rgba<u8>;
+
+        fn index_of(&self, color: &Rgba<u8>) -> usize {
+            (color[0] > 127) as usize
+        }
+
+        fn map_color(&self, color: &mut Rgba<u8>) {
+            let c = if color[0] > 127 { 255 } else { 0 };
+            color.0[..3].copy_from_slice(&[c; 3]);
+        }
+    }
+
+    #[test]
+    fn test_hungarian_square() {
+        let costs = vec![vec![4., 1., 3.], vec![2., 0., 5.], vec![3., 2., 2.]];
+        assert_eq!(hungarian(&costs), vec![1, 0, 2]);
+    }
+
+    #[test]
+    fn test_hungarian_rectangular() {
+        let costs = vec![vec![9., 1., 9., 9.], vec![9., 2., 9., 3.]];
+        assert_eq!(hungarian(&costs), vec![1, 3]);
+    }
+
+    #[test]
+    fn test_balanced_without_lookup() {
+        let img = RgbaImage::from_fn(4, 4, |x, y| Rgba([(x * 60 + y * 5) as u8, 0, 0, 255]));
+        let options = |mode| Options {
+            mode,
+            ..Default::default()
+        };
+        assert_eq!(
+            convert(&img, options(Mode::Balanced), &BlackAndWhite),
+            convert(&img, options(Mode::Nearest), &BlackAndWhite)
+        );
+    }
+}
@@ -0,0 +1,327 @@
+//! Converting many images at once.
+//!
+//! With the `rayon` feature, [`convert_all`] converts images in parallel,
+//! on the global rayon thread pool or one of your own. Images are [loaded](Load)
+//! right before they are converted, and [`MemoryBudget`] keeps parallel
+//! conversions from holding too many huge images at once. It works with any
+//! kind of parallelism, and [`load_all`] schedules your own processing the
+//! same way.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::{Condvar, Mutex},
+};
+
+use image::{ImageResult, RgbaImage};
+
+#[cfg(feature = "rayon")]
+use crate::{convert, Options};
+#[cfg(feature = "rayon")]
+use image::{imageops::ColorMap, Rgba};
+#[cfg(feature = "rayon")]
+use rayon::{iter::IndexedParallelIterator, prelude::*, ThreadPool};
+
+/// Limits the memory used by images that are converted at the same time.
+///
+/// Each conversion [reserves](MemoryBudget::reserve) an estimate of the memory
+/// it needs before it starts, and waits until enough of the budget is free.
+///
+/// ```
+/// # use image_go_nord::batch::MemoryBudget;
+/// let budget = MemoryBudget::new(100);
+/// let first = budget.reserve(60);
+/// assert_eq!(budget.used(), 60);
+/// drop(first);
+/// // images bigger than the budget are converted alone
+/// let huge = budget.reserve(500);
+/// assert_eq!(budget.used(), 500);
+/// ```
+#[derive(Debug)]
+pub struct MemoryBudget {
+    limit: u64,
+    used: Mutex<u64>,
+    freed: Condvar,
+}
+
+impl MemoryBudget {
+    /// A budget of `limit` bytes
+    pub fn new(limit: u64) -> Self {
+        MemoryBudget {
+            limit,
+            used: Mutex::new(0),
+            freed: Condvar::new(),
+        }
+    }
+
+    /// The size of the budget in bytes
+    pub fn limit(&self) -> u64 {
+        self.limit
+    }
+
+    /// The number of bytes that are reserved
+    pub fn used(&self) -> u64 {
+        *self.used.lock().unwrap()
+    }
+
+    /// Block until `bytes` fit in the budget, then reserve them until the
+    /// returned [`Reservation`] is dropped. Reservations bigger than the whole
+    /// budget wait until nothing else is reserved, so they never wait forever.
+    pub fn reserve(&self, bytes: u64) -> Reservation<'_> {
+        let mut used = self.used.lock().unwrap();
+        while *used > 0 && *used + bytes > self.limit {
+            used = self.freed.wait(used).unwrap();
+        }
+        *used += bytes;
+        Reservation {
+            budget: self,
+            bytes,
+        }
+    }
+}
+
+/// Memory reserved in a [`MemoryBudget`], which is freed when this is dropped.
+#[derive(Debug)]
+pub struct Reservation<'a> {
+    budget: &'a MemoryBudget,
+    bytes: u64,
+}
+
+impl Drop for Reservation<'_> {
+    fn drop(&mut self) {
+        *self.budget.used.lock().unwrap() -= self.bytes;
+        self.budget.freed.notify_all();
+    }
+}
+
+/// An image that [`convert_all`] loads right before converting it, so images
+/// waiting for their turn don't take up memory.
+///
+/// With [`load_all`], loading can also do the processing, like decoding and
+/// converting an image in one go.
+pub trait Load: Sync {
+    /// The loaded image, or whatever loading it makes
+    type Output;
+
+    /// An estimate of the size of the loaded image in bytes, which is known
+    /// before loading it
+    fn size(&self) -> u64;
+
+    /// Load the image
+    fn load(&self) -> Self::Output;
+}
+
+/// Images in files are decoded when they are loaded, and their size is read
+/// from the header of the file. Files whose size can't be read have a size of
+/// zero, since they fail to load right away.
+impl Load for Path {
+    type Output = ImageResult<RgbaImage>;
+
+    fn size(&self) -> u64 {
+        let (width, height) = image::image_dimensions(self).unwrap_or_default();
+        4 * width as u64 * height as u64
+    }
+
+    fn load(&self) -> ImageResult<RgbaImage> {
+        Ok(image::open(self)?.into_rgba8())
+    }
+}
+
+impl Load for PathBuf {
+    type Output = ImageResult<RgbaImage>;
+
+    fn size(&self) -> u64 {
+        self.as_path().size()
+    }
+
+    fn load(&self) -> ImageResult<RgbaImage> {
+        self.as_path().load()
+    }
+}
+
+/// Images that are already in memory are copied when they are loaded.
+impl Load for RgbaImage {
+    type Output = ImageResult<RgbaImage>;
+
+    fn size(&self) -> u64 {
+        self.as_raw().len() as u64
+    }
+
+    fn load(&self) -> ImageResult<RgbaImage> {
+        Ok(self.clone())
+    }
+}
+
+impl<T: Load + ?Sized> Load for &T {
+    type Output = T::Output;
+
+    fn size(&self) -> u64 {
+        (**self).size()
+    }
+
+    fn load(&self) -> T::Output {
+        (**self).load()
+    }
+}
+
+/// Where and how many images [`convert_all`] converts at once.
+#[cfg(feature = "rayon")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BatchOptions<'a> {
+    /// The thread pool to convert images on, or the global rayon pool if
+    /// `None`. Build a pool with fewer threads to limit how many images are
+    /// converted at once.
+    pub pool: Option<&'a ThreadPool>,
+    /// Limits the memory used by the images being converted, like in
+    /// [`load_all`]
+    pub memory: Option<&'a MemoryBudget>,
+}
+
+/// Load images in parallel on the current rayon thread pool, waiting until
+/// there is room in the `memory` budget before loading each one.
+///
+/// Each image reserves three times its [size](Load::size): the loaded image,
+/// the converted image, and one in between. The reservation comes with the
+/// loaded image, so keep it until you're done with the image.
+///
+/// ```
+/// # use image::{Rgba, RgbaImage};
+/// # use image_go_nord::{batch::*, convert, Options, NORD};
+/// # use rayon::prelude::*;
+/// let images = vec![RgbaImage::from_pixel(8, 8, Rgba([200, 100, 50, 255])); 4];
+/// let budget = MemoryBudget::new(1024 * 1024);
+/// load_all(&images, Some(&budget)).for_each(|(_, image, _reservation)| {
+///     let converted = convert(&image.unwrap(), Options::default(), &NORD);
+///     // save the image while the memory is still reserved
+/// });
+/// ```
+#[cfg(feature = "rayon")]
+pub fn load_all<'a, L: Load>(
+    images: &'a [L],
+    memory: Option<&'a MemoryBudget>,
+) -> impl IndexedParallelIterator<Item = (&'a L, L::Output, Option<Reservation<'a>>)> + 'a
+where
+    L::Output: Send,
+{
+    images.par_iter().map(move |image| {
+        // reserve before loading, so waiting images aren't loaded
+        let reservation = memory.map(|budget| budget.reserve(3 * image.size()));
+        (image, image.load(), reservation)
+    })
+}
+
+/// Load and convert images in parallel with [`convert`], returning the
+/// converted images, or the errors loading them, in the same order.
+///
+/// ```
+/// # use image::{Rgba, RgbaImage};
+/// # use image_go_nord::{batch::*, Options, NORD};
+/// let images = vec![RgbaImage::from_pixel(8, 8, Rgba([200, 100, 50, 255])); 4];
+/// let pool = rayon::ThreadPoolBuilder::new().num_threads(2).build().unwrap();
+/// let budget = MemoryBudget::new(1024 * 1024);
+/// let options = BatchOptions {
+///     pool: Some(&pool),
+///     memory: Some(&budget),
+/// };
+/// let converted = convert_all(&images, &Options::default(), &NORD, options);
+/// assert_eq!(converted.len(), 4);
+/// ```
+///
+/// Pass paths to decode images only when they are converted:
+///
+/// ```no_run
+/// # use std::path::PathBuf;
+/// # use image_go_nord::{batch::*, Options, NORD};
+/// let paths = vec![PathBuf::from("first.png"), PathBuf::from("second.png")];
+/// let budget = MemoryBudget::new(512 * 1024 * 1024);
+/// let options = BatchOptions {
+///     memory: Some(&budget),
+///     ..Default::default()
+/// };
+/// for converted in convert_all(&paths, &Options::default(), &NORD, options) {
+///     converted?.save("nord.png")?;
+/// }
+/// # Ok::<(), image::ImageError>(())
+/// ```
+#[cfg(feature = "rayon")]
+pub fn convert_all<L, P>(
+    images: &[L],
+    opt: &Options,
+    palette: &P,
+    options: BatchOptions,
+) -> Vec<ImageResult<RgbaImage>>
+where
+    L: Load<Output = ImageResult<RgbaImage>>,
+    P: ColorMap<Color = Rgba<u8>> + Sync,
+{
+    let run = || {
+        load_all(images, options.memory)
+            .map(|(_, image, _reservation)| Ok(convert(&image?, opt.clone(), palette)))
+            .collect()
+    };
+    match options.pool {
+        Some(pool) => pool.install(run),
+        None => run(),
+    }
+}
+
+#[cfg(all(test, feature = "rayon"))]
+mod tests {
+    use super::*;
+    use crate::NORD;
+    use image::Rgba;
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        thread,
+        time::Duration,
+    };
+
+    /// Counts how many images are being loaded at once
+    struct Counted<'a> {
+        running: &'a AtomicUsize,
+        most: &'a AtomicUsize,
+    }
+
+    impl Load for Counted<'_> {
+        type Output = ImageResult<RgbaImage>;
+
+        fn size(&self) -> u64 {
+            100
+        }
+
+        fn load(&self) -> ImageResult<RgbaImage> {
+            let running = self.running.fetch_add(1, Ordering::SeqCst) + 1;
+            self.most.fetch_max(running, Ordering::SeqCst);
+            // give the other threads time to start loading too
+            thread::sleep(Duration::from_millis(20));
+            self.running.fetch_sub(1, Ordering::SeqCst);
+            Ok(RgbaImage::from_pixel(5, 5, Rgba([200, 100, 50, 255])))
+        }
+    }
+
+    #[test]
+    fn test_budget_limits_loading() {
+        let (running, most) = (AtomicUsize::new(0), AtomicUsize::new(0));
+        let images: Vec<_> = (0..8)
+            .map(|_| Counted {
+                running: &running,
+                most: &most,
+            })
+            .collect();
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(4)
+            .build()
+            .unwrap();
+        // room for two images at a time
+        let budget = MemoryBudget::new(2 * 3 * 100);
+        let options = BatchOptions {
+            pool: Some(&pool),
+            memory: Some(&budget),
+        };
+
+        let converted = convert_all(&images, &Default::default(), &NORD, options);
+        assert_eq!(converted.len(), 8);
+        assert!(converted.iter().all(Result::is_ok));
+        assert!(most.into_inner() <= 2);
+        assert_eq!(budget.used(), 0);
+    }
+}
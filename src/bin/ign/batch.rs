@@ -0,0 +1,382 @@
+use color_eyre::eyre::Result;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use rayon::iter::ParallelIterator;
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::Instant,
+};
+
+use image::ImageFormat;
+use image_go_nord::batch::{load_all, Load, MemoryBudget};
+
+use crate::{
+    conversion::{convert_image, Conversion, ConvertedImage},
+    incremental::Incremental,
+    output::{
+        check_overwrite, create_parent, overwrites_input, print_preview, print_report, Saver,
+    },
+    progress::{JsonRecords, ProgressBar, Saved, Summary},
+};
+
+/// Converts the images of an input directory into an output directory
+pub(crate) struct Batch<'a> {
+    pub(crate) input: &'a Path,
+    pub(crate) output: Option<&'a Path>,
+    /// Directory where heatmaps are saved
+    pub(crate) heatmap: Option<&'a PathBuf>,
+    pub(crate) conversion: &'a Conversion,
+    pub(crate) saver: &'a Saver,
+    pub(crate) filter: Filter,
+    pub(crate) recursive: bool,
+    /// Canonical paths of subdirectories that are left out, see [`find_files`]
+    pub(crate) skip: Vec<PathBuf>,
+    pub(crate) incremental: bool,
+    pub(crate) fail_fast: bool,
+    pub(crate) dry_run: bool,
+    pub(crate) memory: Option<MemoryBudget>,
+    pub(crate) json: Option<JsonRecords>,
+    /// Canonical paths of the images that were saved, so `--watch` can ignore
+    /// them
+    pub(crate) written: Mutex<HashSet<PathBuf>>,
+}
+
+impl Batch<'_> {
+    /// List the files of the input directory
+    pub(crate) fn files(&self) -> Result<Vec<PathBuf>> {
+        find_files(self.input, self.recursive, &self.skip)
+    }
+
+    /// The canonical paths of every image in the input directory, whether it
+    /// is converted in this run or not, so none of them are overwritten
+    fn inputs(&self) -> Result<HashSet<PathBuf>> {
+        Ok(self
+            .files()?
+            .into_iter()
+            .filter(|path| is_image(path))
+            .filter_map(|path| fs::canonicalize(self.input.join(path)).ok())
+            .collect())
+    }
+
+    /// Convert the images among `files`, which are relative to the input
+    /// directory, and print a summary
+    pub(crate) fn run(&self, files: Vec<PathBuf>) -> Result<()> {
+        let start = Instant::now();
+        // only try files that look like images, so other files in the
+        // directory don't count as failures
+        let (images, skipped): (Vec<_>, Vec<_>) =
+            files.into_iter().partition(|path| is_image(path));
+        let (images, filtered): (Vec<_>, Vec<_>) = images
+            .into_iter()
+            .partition(|path| self.filter.matches(path));
+        let inputs = self.inputs()?;
+        let mut incremental = match self.output {
+            Some(output) if self.incremental => {
+                Some(Incremental::open(output, self.conversion, self.saver)?)
+            }
+            _ => None,
+        };
+        let (images, unchanged) = match (&mut incremental, self.output) {
+            (Some(incremental), Some(output)) => incremental.changed(self.input, output, images)?,
+            _ => (images, 0),
+        };
+        if self.dry_run {
+            return self.plan(
+                &images,
+                &skipped,
+                &filtered,
+                incremental.map(|_| unchanged),
+                &inputs,
+            );
+        }
+
+        // reports and previews are printed to the terminal as images are
+        // converted, so they would get mixed up with the progress bar
+        let progress = if self.conversion.report || self.conversion.preview.is_some() {
+            None
+        } else {
+            ProgressBar::new(images.len())
+        };
+        let converted = AtomicUsize::new(0);
+        let jobs: Vec<_> = images
+            .iter()
+            .map(|path| Job {
+                path,
+                input_dir: self.input,
+                conversion: self.conversion,
+            })
+            .collect();
+        let finish =
+            |(job, (start, image), _memory): (&Job, (Instant, Result<ConvertedImage>), _)| {
+                let path = job.path;
+                let saved =
+                    image.and_then(|image| self.save(path, image, incremental.as_ref(), &inputs));
+                if saved.is_ok() {
+                    converted.fetch_add(1, Ordering::Relaxed);
+                }
+                if let Some(json) = &self.json {
+                    json.image(&self.input.join(path), self.saver, &saved, start.elapsed());
+                }
+                if let Some(progress) = &progress {
+                    progress.tick();
+                }
+                saved
+                    .map(|_| ())
+                    .map_err(|e| e.wrap_err(format!("failed to convert {}", path.display())))
+            };
+        let errors = if self.fail_fast {
+            load_all(&jobs, self.memory.as_ref())
+                .try_for_each(finish)
+                .map(|_| Vec::new())
+        } else {
+            // collect the errors before printing them, so they are printed in
+            // the same order as the images even if they are converted in
+            // parallel
+            Ok(load_all(&jobs, self.memory.as_ref())
+                .map(finish)
+                .filter_map(Result::err)
+                .collect::<Vec<_>>())
+        };
+        if let Some(progress) = &progress {
+            progress.clear();
+        }
+        // record the images that were converted even if one failed
+        let removed = match (incremental, self.output) {
+            (Some(incremental), Some(output)) => Some(incremental.finish(self.input, output)?),
+            _ => None,
+        };
+        let summary = Summary {
+            converted: converted.into_inner(),
+            unchanged: removed.map(|_| unchanged),
+            removed,
+            skipped: skipped.len(),
+            filtered: filtered.len(),
+            // fail-fast stops at the first failure
+            failed: errors.as_ref().map_or(1, Vec::len),
+            duration: start.elapsed().as_secs_f64(),
+        };
+        match (&self.json, &errors) {
+            (Some(json), _) => json.summary(&summary),
+            (None, Ok(errors)) => {
+                for e in errors {
+                    eprintln!("error: {:#}", e);
+                }
+                eprintln!("{}", summary);
+            }
+            (None, Err(_)) => {}
+        }
+        errors?;
+        Ok(())
+    }
+
+    /// Print what converting `images` would do, for `--dry-run`
+    fn plan(
+        &self,
+        images: &[PathBuf],
+        skipped: &[PathBuf],
+        filtered: &[PathBuf],
+        unchanged: Option<usize>,
+        inputs: &HashSet<PathBuf>,
+    ) -> Result<()> {
+        let mut overwritten = 0;
+        for path in images {
+            let outputs: Vec<_> = self
+                .output
+                .map(|output| output.join(self.saver.name(path)))
+                .into_iter()
+                .chain(self.heatmap.map(|dir| dir.join(path)))
+                .collect();
+            overwritten += print_plan(&self.input.join(path), &outputs, inputs)?;
+        }
+        for path in skipped {
+            println!("{} (skip)", self.input.join(path).display());
+        }
+        for path in filtered {
+            println!("{} (filtered)", self.input.join(path).display());
+        }
+
+        let mut summary = format!("{} to convert, {} to overwrite", images.len(), overwritten);
+        if let Some(unchanged) = unchanged {
+            summary += &format!(", {} unchanged", unchanged);
+        }
+        summary += &format!(", {} skipped", skipped.len());
+        if !filtered.is_empty() {
+            summary += &format!(", {} filtered", filtered.len());
+        }
+        eprintln!("{}", summary);
+        Ok(())
+    }
+
+    /// Save a converted image, which is saved with its path relative to the
+    /// input directory to mirror its subdirectories, without overwriting any
+    /// of the `inputs`
+    fn save(
+        &self,
+        path: &Path,
+        converted: ConvertedImage,
+        incremental: Option<&Incremental>,
+        inputs: &HashSet<PathBuf>,
+    ) -> Result<Saved> {
+        let mut written = Vec::new();
+        if self.json.is_none() {
+            print_report(path, converted.report);
+        }
+        print_preview(path, converted.preview.as_deref())?;
+        if let (Some(dir), Some(heatmap)) = (self.heatmap, &converted.heatmap) {
+            let path = dir.join(path);
+            check_overwrite(&path, inputs)?;
+            create_parent(&path)?;
+            heatmap.save(&path)?;
+            written.push(path);
+        }
+        if let Some(output) = self.output {
+            let name = self.saver.name(path);
+            let output = output.join(&name);
+            create_parent(&output)?;
+            self.saver
+                .save(&converted.img, &output, inputs, &self.conversion.palette)?;
+            if let Some(incremental) = incremental {
+                incremental.converted(path, name);
+            }
+            written.push(output);
+        }
+        let mut canonical = self.written.lock().unwrap();
+        for path in &written {
+            canonical.insert(fs::canonicalize(path)?);
+        }
+        // the heatmap comes first if there is one
+        let output = self.output.and(written.pop());
+        Ok(Saved::new(output, &converted))
+    }
+}
+
+/// Print a line with `input` and the files it would be converted to, for
+/// `--dry-run`, and return how many of them would be overwritten. Outputs that
+/// are one of the `inputs` are refused.
+pub(crate) fn print_plan(
+    input: &Path,
+    outputs: &[PathBuf],
+    inputs: &HashSet<PathBuf>,
+) -> Result<usize> {
+    let mut overwritten = 0;
+    let outputs = outputs
+        .iter()
+        .map(|output| {
+            let mut line = output.display().to_string();
+            if overwrites_input(output, inputs)? {
+                line += " (refusing to overwrite an input)";
+            } else if output != Path::new("-") && output.exists() {
+                line += " (overwrite)";
+                overwritten += 1;
+            }
+            Ok(line)
+        })
+        .collect::<Result<Vec<_>>>()?;
+    if outputs.is_empty() {
+        println!("{}", input.display());
+    } else {
+        println!("{} -> {}", input.display(), outputs.join(", "));
+    }
+    Ok(overwritten)
+}
+
+/// An image of a batch, which is converted as soon as it is loaded, so only
+/// the memory budget decides how many are in memory
+struct Job<'a> {
+    /// Path relative to the input directory
+    path: &'a Path,
+    input_dir: &'a Path,
+    conversion: &'a Conversion,
+}
+
+impl Load for Job<'_> {
+    /// When the conversion started, and the converted image
+    type Output = (Instant, Result<ConvertedImage>);
+
+    /// Files that can't be read as images reserve nothing, since they fail
+    /// right away
+    fn size(&self) -> u64 {
+        self.input_dir.join(self.path).size()
+    }
+
+    fn load(&self) -> Self::Output {
+        let start = Instant::now();
+        let image = convert_image(&self.input_dir.join(self.path), self.conversion);
+        (start, image)
+    }
+}
+
+/// Which files of a directory are converted
+pub(crate) struct Filter {
+    pub(crate) include: Option<GlobSet>,
+    pub(crate) exclude: Option<GlobSet>,
+    /// Lowercase extensions, or empty to allow any image extension
+    pub(crate) extensions: Vec<String>,
+}
+
+impl Filter {
+    /// Whether to convert the image at `path`, relative to the input directory
+    pub(crate) fn matches(&self, path: &Path) -> bool {
+        let extension = path
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        (self.extensions.is_empty() || self.extensions.contains(&extension))
+            && self.include.as_ref().is_none_or(|set| set.is_match(path))
+            && !self.exclude.as_ref().is_some_and(|set| set.is_match(path))
+    }
+}
+
+/// Whether a file looks like an image from its extension
+pub(crate) fn is_image(path: &Path) -> bool {
+    ImageFormat::from_path(path).is_ok()
+}
+
+/// Compile glob patterns into a set, or `None` if there are no patterns
+pub(crate) fn glob_set(patterns: &[String]) -> Result<Option<GlobSet>> {
+    if patterns.is_empty() {
+        return Ok(None);
+    }
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        builder.add(Glob::new(pattern)?);
+    }
+    Ok(Some(builder.build()?))
+}
+
+/// List the files in a directory, and in its subdirectories if `recursive`,
+/// relative to the directory and sorted by path. The subdirectories in
+/// `skip`, which are canonical paths, are left out.
+fn find_files(dir: &Path, recursive: bool, skip: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    fn walk(
+        root: &Path,
+        relative: &Path,
+        recursive: bool,
+        skip: &[PathBuf],
+        files: &mut Vec<PathBuf>,
+    ) -> Result<()> {
+        for entry in root.join(relative).read_dir()? {
+            let entry = entry?;
+            let path = relative.join(entry.file_name());
+            // symlinks to directories aren't followed, so they can't loop
+            if entry.file_type()?.is_dir() {
+                if recursive && !skip.contains(&fs::canonicalize(entry.path())?) {
+                    walk(root, &path, recursive, skip, files)?;
+                }
+            } else {
+                files.push(path);
+            }
+        }
+        Ok(())
+    }
+
+    let mut files = Vec::new();
+    walk(dir, Path::new(""), recursive, skip, &mut files)?;
+    files.sort();
+    Ok(files)
+}
@@ -0,0 +1,250 @@
+use color_eyre::eyre::{bail, Result};
+use serde_json::json;
+use std::{convert::TryInto, fs, path::PathBuf};
+
+use clap::Clap;
+use image::{imageops::ColorMap, Rgba, RgbaImage};
+use image_go_nord::{
+    assignment::Assignment,
+    compare::{compare, CompareOptions, Layout},
+    convert,
+    heatmap::Metric,
+    lut::Lut,
+    palette::{HueOnly, Perceptual, PreserveLuminance},
+    stats::usage,
+    Mode, Options,
+};
+
+use crate::conversion::{Algorithm, Conversion};
+
+#[derive(Clap, Debug)]
+pub(crate) struct CompareOpt {
+    /// Original image path.
+    input: PathBuf,
+
+    /// Compare with an already converted image instead of converting INPUT
+    /// with the given options.
+    #[clap(long, value_name = "PATH")]
+    converted: Option<PathBuf>,
+    /// How the images are arranged: `side-by-side`, `stacked`, `split`, or
+    /// `diagonal`.
+    #[clap(short, long, value_name = "LAYOUT", default_value = "side-by-side")]
+    layout: Layout,
+    /// Add a strip with the palette colors to the bottom of the image.
+    #[clap(short, long)]
+    swatches: bool,
+    /// Label the original and converted images.
+    #[clap(
+        long,
+        value_names = &["BEFORE", "AFTER"],
+        number_of_values = 2,
+        use_delimiter = true
+    )]
+    labels: Option<Vec<String>>,
+
+    /// Output image path.
+    output: PathBuf,
+}
+
+#[derive(Clap, Debug)]
+pub(crate) struct StatsOpt {
+    /// Original image path.
+    input: PathBuf,
+
+    /// Use an already converted image instead of converting INPUT with the
+    /// given options.
+    #[clap(long, value_name = "PATH")]
+    converted: Option<PathBuf>,
+    /// Print the statistics as JSON instead of a table.
+    #[clap(long)]
+    json: bool,
+    /// Print the mapping table of `--mode balanced` instead: which palette
+    /// color each cluster of the image's colors is assigned to.
+    #[clap(long, conflicts_with = "converted")]
+    mapping: bool,
+}
+
+#[derive(Clap, Debug)]
+pub(crate) struct LutOpt {
+    /// How colors are matched to the palette: `euclidean` or `ciede2000`.
+    #[clap(long, value_name = "METRIC", default_value = "euclidean")]
+    metric: Metric,
+    /// The number of points along each axis of a `.cube` LUT.
+    #[clap(long, value_name = "SIZE", default_value = "33")]
+    size: usize,
+    /// The level of a HALD CLUT image, which holds a LUT of size LEVEL².
+    #[clap(long, value_name = "LEVEL", default_value = "8")]
+    level: usize,
+
+    /// Output path. Files ending in `.cube` are saved as a cube LUT, and
+    /// anything else as a HALD CLUT image.
+    output: PathBuf,
+}
+
+#[derive(Clap, Debug)]
+pub(crate) struct ConfigOpt {
+    #[clap(subcommand)]
+    pub(crate) command: ConfigCommand,
+}
+
+#[derive(Clap, Debug)]
+pub(crate) enum ConfigCommand {
+    /// Print the effective settings, merged from the options given before
+    /// `config`, the config files, the presets, and the defaults.
+    Show,
+}
+
+pub(crate) fn run_compare(opts: CompareOpt, conversion: &Conversion) -> Result<()> {
+    let original = image::open(&opts.input)?.to_rgba8();
+    let converted = match &opts.converted {
+        Some(path) => image::open(path)?.to_rgba8(),
+        None => conversion.apply(&original),
+    };
+    // clap makes sure there are exactly two labels
+    let labels = opts.labels.map(|labels| labels.try_into().unwrap());
+    let options = CompareOptions {
+        layout: opts.layout,
+        swatches: opts.swatches,
+        labels,
+    };
+    compare(&original, &converted, &conversion.palette, options).save(&opts.output)?;
+    Ok(())
+}
+
+pub(crate) fn run_stats(opts: StatsOpt, conversion: &Conversion) -> Result<()> {
+    let original = image::open(&opts.input)?.to_rgba8();
+    if opts.mapping {
+        return print_mapping(&original, conversion, opts.json);
+    }
+    let converted = match &opts.converted {
+        Some(path) => image::open(path)?.to_rgba8(),
+        None => conversion.apply(&original),
+    };
+    if original.dimensions() != converted.dimensions() {
+        bail!("INPUT and the converted image must have the same dimensions")
+    }
+    let usage = usage(&original, &converted, &conversion.palette);
+
+    let hex = |Rgba([r, g, b, _]): Rgba<u8>| format!("#{:02X}{:02X}{:02X}", r, g, b);
+    if opts.json {
+        let entries: Vec<_> = usage
+            .iter()
+            .map(|u| {
+                json!({
+                    "index": u.index,
+                    "color": hex(u.color),
+                    "pixels": u.pixels,
+                    "fraction": u.fraction,
+                    "mean_error": u.mean_error,
+                })
+            })
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&entries)?);
+    } else {
+        println!(
+            "{:>5}  {:<7}  {:>10}  {:>6}  {:>7}",
+            "INDEX", "COLOR", "PIXELS", "SHARE", "MEAN ΔE"
+        );
+        for u in &usage {
+            println!(
+                "{:>5}  {:<7}  {:>10}  {:>5.1}%  {:>7.2}",
+                u.index,
+                hex(u.color),
+                u.pixels,
+                u.fraction * 100.,
+                u.mean_error
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Print the mapping table of balanced mode, for `ign stats --mapping`
+fn print_mapping(original: &RgbaImage, conversion: &Conversion, json: bool) -> Result<()> {
+    let options = match &conversion.algorithm {
+        Algorithm::Palette(options) if options.mode == Mode::Balanced => options,
+        _ => bail!("--mapping needs --mode balanced"),
+    };
+    // the clusters are found in the image as it is just before palette
+    // matching, after quantizing it and applying the LUT
+    let prepared = convert(
+        original,
+        Options {
+            skip_palette: true,
+            blur: 0.,
+            ..options.clone()
+        },
+        &conversion.palette,
+    );
+    let entries = (0..)
+        .take_while(|&i| conversion.palette.lookup(i).is_some())
+        .count();
+    let mapping = Assignment::new(&prepared, &conversion.palette, entries).mapping();
+
+    let hex = |Rgba([r, g, b, _]): Rgba<u8>| format!("#{:02X}{:02X}{:02X}", r, g, b);
+    if json {
+        let entries: Vec<_> = mapping
+            .iter()
+            .map(|m| {
+                json!({
+                    "cluster": hex(m.cluster),
+                    "weight": m.weight,
+                    "index": m.index,
+                    "color": hex(m.color),
+                })
+            })
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&entries)?);
+    } else {
+        println!("{:<7}  {:>6}  {:>5}  COLOR", "CLUSTER", "SHARE", "INDEX");
+        for m in &mapping {
+            println!(
+                "{:<7}  {:>5.1}%  {:>5}  {}",
+                hex(m.cluster),
+                m.weight * 100.,
+                m.index,
+                hex(m.color)
+            );
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn run_lut(opts: LutOpt, conversion: &Conversion) -> Result<()> {
+    let mode = match &conversion.algorithm {
+        Algorithm::Palette(options) => options.mode,
+        Algorithm::Gradient(..) => bail!("gradient maps can't be baked into a LUT"),
+    };
+    let cube = opts.output.extension().is_some_and(|ext| ext == "cube");
+    let size = if cube {
+        opts.size
+    } else {
+        opts.level * opts.level
+    };
+    if size < 2 {
+        bail!("LUTs must have at least 2 points along each axis")
+    }
+
+    let lut = match opts.metric {
+        Metric::Euclidean => bake(&conversion.palette, mode, size)?,
+        Metric::Ciede2000 => bake(&Perceptual(&conversion.palette), mode, size)?,
+    };
+    if cube {
+        fs::write(&opts.output, lut.to_cube(Some("Nord")))?
+    } else {
+        lut.to_hald().save(&opts.output)?
+    }
+    Ok(())
+}
+
+/// Bake the mapping of a palette in the given mode into a LUT
+fn bake(palette: &impl ColorMap<Color = Rgba<u8>>, mode: Mode, size: usize) -> Result<Lut> {
+    Ok(match mode {
+        Mode::Nearest => Lut::from_color_map(palette, size),
+        Mode::Luminance => Lut::from_color_map(&PreserveLuminance(palette), size),
+        Mode::Hue => Lut::from_color_map(&HueOnly(palette), size),
+        Mode::Balanced => {
+            bail!("balanced mode depends on the image, so it can't be baked into a LUT")
+        }
+    })
+}
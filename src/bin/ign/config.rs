@@ -0,0 +1,278 @@
+use color_eyre::eyre::{bail, eyre, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use image::imageops::ColorMap;
+use image_go_nord::{
+    ansi::{Characters, Colors},
+    gradient::Order,
+    heatmap::{Colormap, Metric},
+    lut::{Interpolation as LutInterpolation, Lut},
+    palette::DynamicPalette,
+    presets,
+    preview::Protocol,
+    Mode, Options, AURORA, FROST, NORD, POLAR_NIGHT, SNOW_STORM,
+};
+
+use crate::{
+    output::{Format, PngCompression, DEFAULT_JPEG_QUALITY, DEFAULT_NAME_TEMPLATE},
+    Opt,
+};
+
+/// Settings that can be read from config files. Keys are the long names of the
+/// options of `ign`, and every key is optional.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub(crate) struct Config {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) palette: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) preset: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) presets: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) blur: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) quantize: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) mode: Option<Mode>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) lut_interpolation: Option<LutInterpolation>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) lut_only: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) no_dither: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) order: Option<Order>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) feather: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) heatmap_metric: Option<Metric>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) colormap: Option<Colormap>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) preview_protocol: Option<Protocol>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) format: Option<Format>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) name_template: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) jpeg_quality: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) png_compression: Option<PngCompression>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) columns: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) ansi_colors: Option<Colors>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) characters: Option<Characters>,
+}
+
+impl Config {
+    /// The settings used when neither the command line nor a config file gives
+    /// them. Presets have no default.
+    pub(crate) fn defaults() -> Self {
+        let options = Options::default();
+        Config {
+            palette: Some("nord".into()),
+            preset: None,
+            presets: None,
+            blur: Some(options.blur),
+            quantize: Some(options.quantize),
+            mode: Some(options.mode),
+            lut_interpolation: Some(options.lut_interpolation),
+            lut_only: Some(options.skip_palette),
+            no_dither: Some(!options.dither),
+            order: Some(Order::default()),
+            feather: Some(0.),
+            heatmap_metric: Some(Metric::default()),
+            colormap: Some(Colormap::default()),
+            preview_protocol: Some(Protocol::default()),
+            format: Some(Format::default()),
+            name_template: Some(DEFAULT_NAME_TEMPLATE.into()),
+            jpeg_quality: Some(DEFAULT_JPEG_QUALITY),
+            png_compression: Some(PngCompression::default()),
+            columns: Some(80),
+            ansi_colors: Some(Colors::default()),
+            characters: Some(Characters::default()),
+        }
+    }
+
+    /// Fill in the settings missing from `self` with those of `other`
+    pub(crate) fn or(self, other: Config) -> Self {
+        Config {
+            palette: self.palette.or(other.palette),
+            preset: self.preset.or(other.preset),
+            presets: self.presets.or(other.presets),
+            blur: self.blur.or(other.blur),
+            quantize: self.quantize.or(other.quantize),
+            mode: self.mode.or(other.mode),
+            lut_interpolation: self.lut_interpolation.or(other.lut_interpolation),
+            lut_only: self.lut_only.or(other.lut_only),
+            no_dither: self.no_dither.or(other.no_dither),
+            order: self.order.or(other.order),
+            feather: self.feather.or(other.feather),
+            heatmap_metric: self.heatmap_metric.or(other.heatmap_metric),
+            colormap: self.colormap.or(other.colormap),
+            preview_protocol: self.preview_protocol.or(other.preview_protocol),
+            format: self.format.or(other.format),
+            name_template: self.name_template.or(other.name_template),
+            jpeg_quality: self.jpeg_quality.or(other.jpeg_quality),
+            png_compression: self.png_compression.or(other.png_compression),
+            columns: self.columns.or(other.columns),
+            ansi_colors: self.ansi_colors.or(other.ansi_colors),
+            characters: self.characters.or(other.characters),
+        }
+    }
+
+    /// Read a config file. Relative paths in it are relative to the file.
+    fn open(path: &Path) -> Result<Self> {
+        let mut config: Config = toml::from_str(&fs::read_to_string(path)?)
+            .map_err(|e| eyre!("{}: {}", path.display(), e))?;
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+        config.presets = config.presets.map(|presets| dir.join(presets));
+        config.palette = config.palette.map(|palette| {
+            if BUILT_IN_PALETTES.contains(&palette.as_str()) {
+                palette
+            } else {
+                dir.join(palette).to_string_lossy().into_owned()
+            }
+        });
+        Ok(config)
+    }
+}
+
+/// The settings given on the command line
+impl From<&Opt> for Config {
+    fn from(opts: &Opt) -> Self {
+        Config {
+            palette: opts.palette.clone(),
+            preset: opts.preset.clone(),
+            presets: opts.presets.clone(),
+            blur: opts.blur,
+            quantize: opts.quantize,
+            mode: opts.mode,
+            lut_interpolation: opts.lut_interpolation,
+            // flags can only turn these on, so leave them to the other
+            // settings otherwise
+            lut_only: opts.lut_only.then_some(true),
+            no_dither: opts.no_dither.then_some(true),
+            order: opts.order,
+            feather: opts.feather,
+            heatmap_metric: opts.heatmap_metric,
+            colormap: opts.colormap,
+            preview_protocol: opts.preview_protocol,
+            format: opts.format,
+            name_template: opts.name_template.clone(),
+            jpeg_quality: opts.jpeg_quality,
+            png_compression: opts.png_compression,
+            columns: opts.columns,
+            ansi_colors: opts.ansi_colors,
+            characters: opts.characters,
+        }
+    }
+}
+
+/// The settings of a preset, which gives every setting it has
+impl From<Options> for Config {
+    fn from(preset: Options) -> Self {
+        Config {
+            blur: Some(preset.blur),
+            quantize: Some(preset.quantize),
+            mode: Some(preset.mode),
+            lut_interpolation: Some(preset.lut_interpolation),
+            lut_only: Some(preset.skip_palette),
+            no_dither: Some(!preset.dither),
+            ..Config::default()
+        }
+    }
+}
+
+/// Read the given config file, or else merge the closest `.ign.toml` with the
+/// user config file, returning the files that were read along with the
+/// settings
+pub(crate) fn load_config(path: Option<&Path>) -> Result<(Vec<PathBuf>, Config)> {
+    if let Some(path) = path {
+        return Ok((vec![path.to_owned()], Config::open(path)?));
+    }
+
+    let user = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+        .map(|dir| dir.join("ign").join("config.toml"));
+    let cwd = env::current_dir()?;
+    let project = cwd
+        .ancestors()
+        .map(|dir| dir.join(".ign.toml"))
+        .find(|path| path.is_file());
+
+    // project settings take precedence over user settings
+    let mut files = Vec::new();
+    let mut config = Config::default();
+    for path in project
+        .into_iter()
+        .chain(user.filter(|path| path.is_file()))
+    {
+        config = config.or(Config::open(&path)?);
+        files.push(path);
+    }
+    Ok((files, config))
+}
+
+/// Names of the palettes that `--palette` accepts besides paths
+const BUILT_IN_PALETTES: [&str; 5] = ["nord", "aurora", "frost", "polar-night", "snow-storm"];
+
+/// Look up a built-in palette by name, or read a palette file
+pub(crate) fn open_palette(name: &str) -> Result<DynamicPalette> {
+    let palette: DynamicPalette = match name {
+        "nord" => NORD.clone().into(),
+        "aurora" => AURORA.clone().into(),
+        "frost" => FROST.clone().into(),
+        "polar-night" => POLAR_NIGHT.clone().into(),
+        "snow-storm" => SNOW_STORM.clone().into(),
+        path => DynamicPalette::from_palette_str(&fs::read_to_string(path)?)
+            .map_err(|e| eyre!("{}: {}", path, e))?,
+    };
+    if palette.lookup(0).is_none() {
+        bail!("palette {} has no colors", name)
+    }
+    Ok(palette)
+}
+
+/// Look up a preset in the presets file, if there is one, or in the built-in
+/// presets
+pub(crate) fn find_preset(name: &str, file: Option<&Path>) -> Result<Options> {
+    if let Some(path) = file {
+        let contents = fs::read_to_string(path)?;
+        let presets = if path.extension().is_some_and(|ext| ext == "json") {
+            presets::from_json(&contents)
+        } else {
+            presets::from_toml(&contents)
+        }
+        .map_err(|e| eyre!("{}: {}", path.display(), e))?;
+        if let Some(options) = presets.get(name) {
+            return Ok(options.clone());
+        }
+    }
+    presets::built_in(name).ok_or_else(|| {
+        eyre!(
+            "unknown preset '{}', expected one of: {}, or one defined with --presets",
+            name,
+            presets::BUILT_IN.join(", ")
+        )
+    })
+}
+
+/// Read a LUT from a `.cube` file or a HALD CLUT image
+pub(crate) fn open_lut(path: &Path) -> Result<Lut> {
+    let lut = if path.extension().is_some_and(|ext| ext == "cube") {
+        Lut::from_cube(&fs::read_to_string(path)?)
+    } else {
+        Lut::from_hald(&image::open(path)?.to_rgb8())
+    };
+    lut.map_err(|e| eyre!("{}: {}", path.display(), e))
+}
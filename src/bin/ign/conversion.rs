@@ -0,0 +1,127 @@
+use color_eyre::eyre::Result;
+use std::{
+    ffi::OsString,
+    io::{self, Cursor, Read},
+    path::Path,
+};
+
+use image::{io::Reader, GrayImage, ImageFormat, RgbaImage};
+use image_go_nord::{
+    convert,
+    gradient::{gradient_map, Interpolation, Order},
+    heatmap::{heatmap, Colormap, Metric},
+    metrics::{measure, Report},
+    palette::DynamicPalette,
+    preview::{preview, Protocol},
+    region::{convert_masked, convert_region, Region},
+    Options,
+};
+
+/// How each image is converted
+#[derive(Clone, Debug)]
+pub(crate) struct Conversion {
+    pub(crate) algorithm: Algorithm,
+    /// The palette images are converted to
+    pub(crate) palette: DynamicPalette,
+    pub(crate) selection: Selection,
+    pub(crate) feather: f32,
+    /// Measure the quality of each conversion
+    pub(crate) report: bool,
+    /// Render a heatmap of the error of each conversion
+    pub(crate) heatmap: Option<(Metric, Colormap)>,
+    /// Render a terminal preview of each conversion, at most this many
+    /// columns wide
+    pub(crate) preview: Option<(Protocol, u32)>,
+}
+
+/// The algorithm used to convert each image
+#[derive(Clone, Debug)]
+pub(crate) enum Algorithm {
+    /// Match colors to the palette using `convert`
+    Palette(Options),
+    /// Map lightness onto the palette using `gradient_map`
+    Gradient(Order, Interpolation),
+}
+
+/// The part of each image that is converted
+#[derive(Clone, Debug)]
+pub(crate) enum Selection {
+    All,
+    Region(Region),
+    Mask(GrayImage),
+}
+
+impl Conversion {
+    pub(crate) fn apply(&self, img: &RgbaImage) -> RgbaImage {
+        let algorithm = |img: &RgbaImage| match &self.algorithm {
+            Algorithm::Palette(options) => convert(img, options.clone(), &self.palette),
+            Algorithm::Gradient(order, interpolation) => {
+                gradient_map(img, &self.palette, *order, *interpolation)
+            }
+        };
+        match &self.selection {
+            Selection::All => algorithm(img),
+            Selection::Region(region) => convert_region(img, *region, self.feather, algorithm),
+            Selection::Mask(mask) => convert_masked(img, mask, self.feather, algorithm),
+        }
+    }
+
+    /// Convert an image, along with the extras that were requested
+    fn finish(
+        &self,
+        original: RgbaImage,
+        name: OsString,
+        format: Option<ImageFormat>,
+    ) -> ConvertedImage {
+        let img = self.apply(&original);
+        let report = self.report.then(|| measure(&original, &img));
+        let heatmap = self
+            .heatmap
+            .map(|(metric, colormap)| heatmap(&original, &img, metric, colormap));
+        let preview = self
+            .preview
+            .map(|(protocol, columns)| preview(&img, protocol, columns));
+        ConvertedImage {
+            name,
+            format,
+            img,
+            report,
+            heatmap,
+            preview,
+        }
+    }
+}
+
+pub(crate) fn convert_image(path: &Path, conversion: &Conversion) -> Result<ConvertedImage> {
+    let original = image::open(path)?.to_rgba8();
+    let name = path.file_name().unwrap_or_default().to_owned();
+    let format = ImageFormat::from_path(path).ok();
+    Ok(conversion.finish(original, name, format))
+}
+
+/// Convert an image read from stdin, guessing its format from its contents
+pub(crate) fn convert_stdin(conversion: &Conversion) -> Result<ConvertedImage> {
+    let mut data = Vec::new();
+    io::stdin().read_to_end(&mut data)?;
+    let reader = Reader::new(Cursor::new(data)).with_guessed_format()?;
+    let format = reader.format();
+    let original = reader.decode()?.to_rgba8();
+    // name the image like a file, for saving it in an output directory
+    let extension = format.map_or("png", |format| format.extensions_str()[0]);
+    let name = format!("stdin.{}", extension).into();
+    Ok(conversion.finish(original, name, format))
+}
+
+/// Holds name as well as image data so it can be saved with the same name
+pub(crate) struct ConvertedImage {
+    pub(crate) name: OsString,
+    /// Format of the original image, if it is known
+    pub(crate) format: Option<ImageFormat>,
+    pub(crate) img: RgbaImage,
+    /// Quality metrics, if they were requested
+    pub(crate) report: Option<Report>,
+    /// Error heatmap, if it was requested
+    pub(crate) heatmap: Option<RgbaImage>,
+    /// Terminal preview, if it was requested
+    pub(crate) preview: Option<String>,
+}
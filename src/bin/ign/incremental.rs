@@ -0,0 +1,181 @@
+use clap::crate_version;
+use color_eyre::eyre::{eyre, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use crate::{conversion::Conversion, output::Saver};
+
+/// The file in an output directory where `--incremental` records what it
+/// converted
+const MANIFEST_NAME: &str = ".ign-manifest.json";
+
+/// The images converted into an output directory, and the settings they were
+/// converted with
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct Manifest {
+    /// Hash of the settings, see [`settings_hash`]
+    settings: String,
+    /// Converted images by their path relative to the input directory
+    images: BTreeMap<PathBuf, ManifestEntry>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+struct ManifestEntry {
+    /// Hash of the contents of the input image, or empty if it has to be
+    /// converted again
+    hash: String,
+    /// Path of the converted image relative to the output directory
+    output: PathBuf,
+}
+
+/// Keeps track of the images converted by `--incremental`
+pub(crate) struct Incremental {
+    /// The manifest of the previous run
+    previous: Manifest,
+    /// The manifest of this run, which is filled in as images are converted
+    current: Mutex<Manifest>,
+    /// Hashes of the images that are converted
+    hashes: HashMap<PathBuf, String>,
+}
+
+impl Incremental {
+    /// Read the manifest of an output directory, if there is one
+    pub(crate) fn open(output_dir: &Path, conversion: &Conversion, saver: &Saver) -> Result<Self> {
+        let path = output_dir.join(MANIFEST_NAME);
+        let previous = if path.exists() {
+            serde_json::from_str(&fs::read_to_string(&path)?)
+                .map_err(|e| eyre!("{}: {}", path.display(), e))?
+        } else {
+            Manifest::default()
+        };
+        let current = Manifest {
+            settings: settings_hash(conversion, saver)?,
+            images: BTreeMap::new(),
+        };
+        Ok(Incremental {
+            previous,
+            current: Mutex::new(current),
+            hashes: HashMap::new(),
+        })
+    }
+
+    /// Hash the images and return the ones that changed since they were last
+    /// converted, along with the number of unchanged images. Images that
+    /// can't be read count as changed, so converting them reports the error.
+    pub(crate) fn changed(
+        &mut self,
+        input_dir: &Path,
+        output_dir: &Path,
+        images: Vec<PathBuf>,
+    ) -> Result<(Vec<PathBuf>, usize)> {
+        let current = self.current.get_mut().unwrap();
+        let same_settings = self.previous.settings == current.settings;
+        let mut changed = Vec::new();
+        for path in images {
+            let hash = match hash_file(&input_dir.join(&path)) {
+                Ok(hash) => hash,
+                Err(_) => {
+                    changed.push(path);
+                    continue;
+                }
+            };
+            match self.previous.images.get(&path) {
+                Some(entry)
+                    if same_settings
+                        && entry.hash == hash
+                        && output_dir.join(&entry.output).exists() =>
+                {
+                    current.images.insert(path, entry.clone());
+                }
+                _ => {
+                    self.hashes.insert(path.clone(), hash);
+                    changed.push(path);
+                }
+            }
+        }
+        let unchanged = current.images.len();
+        Ok((changed, unchanged))
+    }
+
+    /// Record that an image was converted to `output`, relative to the output
+    /// directory. Images that couldn't be hashed aren't recorded, so they are
+    /// converted again next time.
+    pub(crate) fn converted(&self, path: &Path, output: PathBuf) {
+        let hash = match self.hashes.get(path) {
+            Some(hash) => hash.clone(),
+            None => return,
+        };
+        let entry = ManifestEntry { hash, output };
+        let mut current = self.current.lock().unwrap();
+        current.images.insert(path.to_owned(), entry);
+    }
+
+    /// Delete the converted images of inputs that were deleted, or that are
+    /// now saved under another name, and save the manifest. Returns the number
+    /// of deleted images.
+    pub(crate) fn finish(self, input_dir: &Path, output_dir: &Path) -> Result<usize> {
+        let mut current = self.current.into_inner().unwrap();
+        let same_settings = self.previous.settings == current.settings;
+        let outputs: HashSet<_> = current
+            .images
+            .values()
+            .map(|entry| entry.output.clone())
+            .collect();
+        let mut removed = 0;
+        for (path, mut entry) in self.previous.images {
+            if !current.images.contains_key(&path) && input_dir.join(&path).is_file() {
+                // keep track of images that weren't converted this time, like
+                // ones that failed, and convert them again if the settings
+                // changed
+                if !same_settings {
+                    entry.hash.clear();
+                }
+                current.images.insert(path, entry);
+            } else if !outputs.contains(&entry.output) {
+                // the image was converted under another name, or its input
+                // was deleted
+                match fs::remove_file(output_dir.join(&entry.output)) {
+                    Ok(()) => removed += 1,
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                    Err(e) => return Err(e.into()),
+                }
+            }
+        }
+        fs::write(
+            output_dir.join(MANIFEST_NAME),
+            serde_json::to_string_pretty(&current)?,
+        )?;
+        Ok(removed)
+    }
+}
+
+/// Hash everything that affects converted images, so that changing any
+/// setting converts them again
+fn settings_hash(conversion: &Conversion, saver: &Saver) -> Result<String> {
+    let mut hasher = Sha256::new();
+    write!(
+        hasher,
+        "{} {:?} {:?} {:?} {:?} {:?} {:?}",
+        crate_version!(),
+        conversion.algorithm,
+        conversion.palette,
+        conversion.selection,
+        conversion.feather,
+        conversion.heatmap,
+        saver
+    )?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+fn hash_file(path: &Path) -> Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
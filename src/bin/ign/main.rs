@@ -0,0 +1,603 @@
+#![cfg(feature = "clap")]
+
+mod batch;
+mod commands;
+mod config;
+mod conversion;
+mod incremental;
+mod output;
+mod progress;
+mod watch;
+
+use color_eyre::eyre::{bail, Result};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::Instant,
+};
+use terminal_size::{terminal_size, Width};
+
+use clap::{crate_authors, crate_version, AppSettings, ArgSettings, Clap};
+use image_go_nord::{
+    ansi::{AnsiOptions, Characters, Colors},
+    batch::MemoryBudget,
+    gradient::{Interpolation, Order},
+    heatmap::{Colormap, Metric},
+    lut::Interpolation as LutInterpolation,
+    preview::Protocol,
+    region::{mask_from_image, Region},
+    Mode, Options,
+};
+
+use crate::{
+    batch::{glob_set, is_image, print_plan, Batch, Filter},
+    commands::{
+        run_compare, run_lut, run_stats, CompareOpt, ConfigCommand, ConfigOpt, LutOpt, StatsOpt,
+    },
+    config::{find_preset, load_config, open_lut, open_palette, Config},
+    conversion::{convert_image, convert_stdin, Algorithm, Conversion, Selection},
+    output::{
+        canonical_inputs, check_overwrite, print_preview, print_report, Format, PngCompression,
+        Saver, DEFAULT_JPEG_QUALITY, DEFAULT_NAME_TEMPLATE,
+    },
+    progress::{JsonRecords, Saved, Summary},
+    watch::watch,
+};
+
+#[derive(Clap, Debug)]
+#[clap(name = "Image Go Nord", version = crate_version!(), author = crate_authors!(","))]
+#[clap(setting = AppSettings::ColoredHelp)]
+#[clap(setting = AppSettings::SubcommandsNegateReqs)]
+struct Opt {
+    /// Input file or directory path, or `-` to read an image from stdin.
+    #[clap(setting = ArgSettings::Required)]
+    input: Option<PathBuf>,
+
+    /// Start from a named preset: `icon`, `photo`, `wallpaper`, `pixel-art`,
+    /// or one defined in the presets file. Other options override the values
+    /// of the preset, and the preset overrides the values of config files.
+    #[clap(short = 'P', long, value_name = "NAME")]
+    preset: Option<String>,
+    /// A TOML or JSON file of user-defined presets, where each table or field
+    /// is a preset named by its key.
+    #[clap(long, value_name = "PATH")]
+    presets: Option<PathBuf>,
+    /// The palette to convert to: `nord` (the default), `aurora`, `frost`,
+    /// `polar-night`, `snow-storm`, or the path of a file with one `#RRGGBB`
+    /// color per line.
+    #[clap(long, value_name = "NAME|PATH")]
+    palette: Option<String>,
+    /// Read settings from this config file instead of `ign/config.toml` in the
+    /// XDG config directory and the closest `.ign.toml` in the current
+    /// directory or its parents.
+    #[clap(long, value_name = "PATH")]
+    config: Option<PathBuf>,
+
+    /// Blur by a given 𝛔 value between 0 and 1. Defaults to 0, which doesn't
+    /// blur.
+    #[clap(short, long, value_name = "SIGMA")]
+    blur: Option<f32>,
+    /// Quantize the image with a given factor of samples, between 1 and 30. Any
+    /// other value, like the default of 0, will disable quantization.
+    #[clap(short, long, value_name = "SAMPLEFAC")]
+    quantize: Option<i32>,
+    /// How pixels are recolored: `nearest` (the default) replaces them with the
+    /// closest palette color, `luminance` keeps their luminance, `hue` only
+    /// replaces their hue, and `balanced` spreads the image's dominant colors
+    /// across the whole palette.
+    #[clap(short, long, value_name = "MODE")]
+    mode: Option<Mode>,
+    /// Map the lightness of each pixel onto the palette as a gradient instead
+    /// of matching colors, using `smooth` or `stepped` interpolation.
+    #[clap(
+        short,
+        long,
+        value_name = "INTERPOLATION",
+        conflicts_with_all = &["blur", "quantize", "mode", "lut", "preset"]
+    )]
+    gradient: Option<Interpolation>,
+    /// Apply a 3D LUT before matching colors to the palette. Files ending in
+    /// `.cube` are read as a cube LUT, and anything else as a HALD CLUT image.
+    #[clap(long, value_name = "PATH")]
+    lut: Option<PathBuf>,
+    /// How colors between the points of the LUT are interpolated:
+    /// `trilinear` or `tetrahedral` (the default).
+    #[clap(long, value_name = "INTERPOLATION")]
+    lut_interpolation: Option<LutInterpolation>,
+    /// Only apply the LUT, without matching colors to the palette.
+    #[clap(long, requires = "lut")]
+    lut_only: bool,
+    /// Replace each pixel with its palette color without dithering, which
+    /// keeps flat areas and hard edges, like in pixel art.
+    #[clap(long)]
+    no_dither: bool,
+    /// The order of the palette colors along the gradient: sorted by
+    /// `luminance` or in `palette` order.
+    #[clap(long, value_name = "ORDER")]
+    order: Option<Order>,
+
+    /// Only convert a rectangular region of the image, given as
+    /// `x,y,width,height` in pixels.
+    #[clap(long, value_name = "X,Y,W,H", conflicts_with = "mask")]
+    region: Option<Region>,
+    /// Only convert the pixels selected by a mask image. White pixels (or
+    /// opaque pixels, if the mask has an alpha channel) are converted and black
+    /// (or transparent) pixels are left untouched.
+    #[clap(long, value_name = "PATH")]
+    mask: Option<PathBuf>,
+    /// Soften the edges of the region or mask, so the conversion fades into
+    /// the original image over about PIXELS pixels.
+    #[clap(long, value_name = "PIXELS")]
+    feather: Option<f32>,
+
+    /// When converting a directory, stop at the first image that can't be
+    /// converted and exit with an error. By default, failures are reported and
+    /// the remaining images are still converted.
+    #[clap(long)]
+    fail_fast: bool,
+    /// When converting a directory, convert up to N images at once. 0 uses
+    /// one thread per CPU, which is the default.
+    #[clap(short, long, value_name = "N")]
+    jobs: Option<usize>,
+    /// When converting a directory, wait for images to finish before
+    /// converting more if the images being converted would take more than MB
+    /// megabytes of memory. Images bigger than that are converted alone.
+    #[clap(long, value_name = "MB")]
+    max_memory: Option<u64>,
+    /// Print a JSON object on a line of stdout for each image that is
+    /// converted or fails to, and one with a summary at the end, instead of
+    /// printing errors, reports, and the summary as text.
+    #[clap(long)]
+    json: bool,
+    /// Print which images would be converted and where they would be saved,
+    /// marking existing files that would be overwritten, and which files would
+    /// be skipped, without converting or saving anything.
+    #[clap(short = 'n', long)]
+    dry_run: bool,
+    /// Keep running, and convert images again whenever they are created or
+    /// changed in INPUT. Images are converted once they haven't been written
+    /// to for half a second.
+    #[clap(short, long)]
+    watch: bool,
+    /// When converting a directory, skip images that haven't changed since
+    /// they were last converted with the same settings, and delete converted
+    /// images whose inputs were deleted. What was converted is recorded in
+    /// `.ign-manifest.json` in OUTPUT.
+    #[clap(long)]
+    incremental: bool,
+    /// When converting a directory, also convert the images in its
+    /// subdirectories, saving them to the same subdirectories of OUTPUT. If
+    /// OUTPUT or the heatmap directory is inside INPUT, it is left out, so
+    /// converted images aren't converted again.
+    #[clap(short, long)]
+    recursive: bool,
+    /// When converting a directory, only convert files whose path relative to
+    /// INPUT matches a glob pattern, like `icons/**/*.png`. Can be given more
+    /// than once.
+    #[clap(
+        long,
+        value_name = "GLOB",
+        multiple_occurrences = true,
+        number_of_values = 1
+    )]
+    include: Vec<String>,
+    /// When converting a directory, skip files whose path relative to INPUT
+    /// matches a glob pattern, even if they are included. Can be given more
+    /// than once.
+    #[clap(
+        long,
+        value_name = "GLOB",
+        multiple_occurrences = true,
+        number_of_values = 1
+    )]
+    exclude: Vec<String>,
+    /// When converting a directory, only convert files with one of these
+    /// extensions, separated by commas, like `png,jpg` or `.png,.jpg`.
+    #[clap(
+        long,
+        value_name = "EXT,...",
+        use_delimiter = true,
+        require_delimiter = true
+    )]
+    extensions: Vec<String>,
+
+    /// Print quality metrics (ΔE, PSNR, and SSIM) comparing each converted
+    /// image with its original.
+    #[clap(long)]
+    report: bool,
+    /// Save a heatmap of the difference between each converted image and its
+    /// original. In directory mode, this is a directory where heatmaps are
+    /// saved with the same names as the converted images.
+    #[clap(long, value_name = "PATH")]
+    heatmap: Option<PathBuf>,
+    /// How differences are measured in the heatmap: `euclidean` or
+    /// `ciede2000` (the default).
+    #[clap(long, value_name = "METRIC")]
+    heatmap_metric: Option<Metric>,
+    /// The colors of the heatmap: `grayscale`, `viridis` (the default),
+    /// `inferno`, or `nord`.
+    #[clap(long, value_name = "COLORMAP")]
+    colormap: Option<Colormap>,
+
+    /// Draw each converted image in the terminal, scaled to fit its width.
+    /// OUTPUT can be left out to only preview the conversion.
+    #[clap(short, long)]
+    preview: bool,
+    /// How previews are drawn: `blocks` (the default) uses colored half block
+    /// characters and works in most terminals, while `sixel` and `kitty` use
+    /// those graphics protocols.
+    #[clap(long, value_name = "PROTOCOL")]
+    preview_protocol: Option<Protocol>,
+
+    /// The format converted images are saved in: `image` (the default) uses
+    /// the format matching the file extension, `png`, `jpeg`, `gif`, `bmp`,
+    /// `tiff`, or `tga` convert images to that format, and `ansi` saves ANSI
+    /// art as text. Unless OUTPUT is a file, images are saved with the
+    /// extension of the format, like `.jpg` or `.ans`. If OUTPUT is a file,
+    /// its extension must match the format. WebP isn't supported, since the
+    /// image crate can't encode it yet.
+    #[clap(short, long, value_name = "FORMAT")]
+    format: Option<Format>,
+    /// How output files are named when OUTPUT is a directory. `{stem}` is
+    /// replaced with the input file name without its extension, `{ext}` with
+    /// the extension of the output format, and `{palette}` with the name of
+    /// the palette. Defaults to `{stem}.{ext}`.
+    #[clap(long, value_name = "TEMPLATE")]
+    name_template: Option<String>,
+    /// The quality of JPEG images, between 1 and 100. Defaults to 75.
+    #[clap(long, value_name = "QUALITY")]
+    jpeg_quality: Option<u8>,
+    /// How much PNG images are compressed: `fast` (the default), `medium`, or
+    /// `best`.
+    #[clap(long, value_name = "LEVEL")]
+    png_compression: Option<PngCompression>,
+    /// The width of ANSI art in characters. Defaults to 80.
+    #[clap(long, value_name = "COLUMNS")]
+    columns: Option<u32>,
+    /// How ANSI art is colored: `truecolor` (the default) uses 24-bit palette
+    /// colors and `terminal` uses the 16 colors of the Nord terminal theme.
+    #[clap(long, value_name = "COLORS")]
+    ansi_colors: Option<Colors>,
+    /// How ANSI art characters are chosen: by `luminance` (the default) or by
+    /// `shape`, using quadrant blocks.
+    #[clap(long, value_name = "CHARACTERS")]
+    characters: Option<Characters>,
+
+    /// Output file or directory path, or `-` to write the image to stdout.
+    /// Images written to stdout keep the format of the input unless `--format`
+    /// is given.
+    #[clap(required_unless_present = "preview")]
+    output: Option<PathBuf>,
+
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Clap, Debug)]
+enum Command {
+    /// Render an image and its conversion into a single comparison image.
+    Compare(CompareOpt),
+    /// Print how much each palette color is used when converting an image.
+    Stats(StatsOpt),
+    /// Bake the palette mapping into a 3D LUT, for use in other tools.
+    Lut(LutOpt),
+    /// Inspect the settings read from config files.
+    Config(ConfigOpt),
+}
+
+fn main() -> Result<()> {
+    let opts = Opt::parse();
+    let (files, config) = load_config(opts.config.as_deref())?;
+    let flags = Config::from(&opts);
+    let presets = flags.presets.clone().or_else(|| config.presets.clone());
+    let preset = |name: Option<&str>| -> Result<Config> {
+        Ok(match name {
+            Some(name) => find_preset(name, presets.as_deref())?.into(),
+            None => Config::default(),
+        })
+    };
+    // options given on the command line take precedence over a preset given
+    // there, then over config files and the preset they give
+    let settings = flags
+        .clone()
+        .or(preset(flags.preset.as_deref())?)
+        .or(config.clone())
+        .or(preset(config.preset.as_deref())?)
+        .or(Config::defaults());
+    if let Some(Command::Config(ConfigOpt {
+        command: ConfigCommand::Show,
+    })) = &opts.command
+    {
+        for file in &files {
+            println!("# read from {}", file.display());
+        }
+        print!("{}", toml::to_string(&settings)?);
+        return Ok(());
+    }
+
+    let algorithm = match opts.gradient {
+        Some(interpolation) => {
+            Algorithm::Gradient(settings.order.unwrap_or_default(), interpolation)
+        }
+        None => Algorithm::Palette(Options {
+            blur: settings.blur.unwrap_or_default(),
+            quantize: settings.quantize.unwrap_or_default(),
+            mode: settings.mode.unwrap_or_default(),
+            lut: opts.lut.as_deref().map(open_lut).transpose()?,
+            lut_interpolation: settings.lut_interpolation.unwrap_or_default(),
+            skip_palette: settings.lut_only.unwrap_or_default(),
+            dither: !settings.no_dither.unwrap_or_default(),
+            ..Options::default()
+        }),
+    };
+    let selection = match (opts.region, &opts.mask) {
+        (Some(region), _) => Selection::Region(region),
+        (None, Some(mask)) => Selection::Mask(mask_from_image(&image::open(mask)?)),
+        (None, None) => Selection::All,
+    };
+    let conversion = &Conversion {
+        algorithm,
+        palette: open_palette(settings.palette.as_deref().unwrap_or("nord"))?,
+        selection,
+        feather: settings.feather.unwrap_or_default(),
+        report: opts.report,
+        heatmap: opts.heatmap.as_ref().map(|_| {
+            (
+                settings.heatmap_metric.unwrap_or_default(),
+                settings.colormap.unwrap_or_default(),
+            )
+        }),
+        preview: opts.preview.then(|| {
+            let columns = terminal_size().map_or(80, |(Width(w), _)| w as u32);
+            (settings.preview_protocol.unwrap_or_default(), columns)
+        }),
+    };
+
+    match opts.command {
+        Some(Command::Compare(compare_opts)) => return run_compare(compare_opts, conversion),
+        Some(Command::Stats(stats_opts)) => return run_stats(stats_opts, conversion),
+        Some(Command::Lut(lut_opts)) => return run_lut(lut_opts, conversion),
+        Some(Command::Config(_)) => unreachable!("handled before converting"),
+        None => {}
+    }
+    // INPUT is required unless a subcommand is used, and OUTPUT is also
+    // required unless previewing
+    let (input, output) = (opts.input.unwrap(), opts.output);
+    let heatmap_path = opts.heatmap.as_ref();
+    let palette_name = settings.palette.as_deref().unwrap_or("nord");
+    let saver = &Saver {
+        format: settings.format.unwrap_or_default(),
+        template: settings
+            .name_template
+            .unwrap_or_else(|| DEFAULT_NAME_TEMPLATE.into()),
+        // palette files are named by their file name
+        palette_name: Path::new(palette_name)
+            .file_stem()
+            .map_or(palette_name.into(), |stem| stem.to_string_lossy().into()),
+        ansi: AnsiOptions {
+            columns: settings.columns.unwrap_or(80),
+            colors: settings.ansi_colors.unwrap_or_default(),
+            characters: settings.characters.unwrap_or_default(),
+        },
+        jpeg_quality: settings.jpeg_quality.unwrap_or(DEFAULT_JPEG_QUALITY),
+        png_compression: settings.png_compression.unwrap_or_default(),
+    };
+    saver.check()?;
+    let json = if opts.json {
+        let (lut, mask) = (opts.lut.as_deref(), opts.mask.as_deref());
+        Some(JsonRecords::new(conversion, saver, lut, mask))
+    } else {
+        None
+    };
+
+    let stdin = input == Path::new("-");
+    let stdout = output.as_deref() == Some(Path::new("-"));
+    if stdout && (opts.report || opts.preview) {
+        bail!("--report and --preview can't be used when writing to stdout")
+    }
+    if opts.json && (stdout || opts.preview) {
+        bail!("--json can't be used with --preview or when writing to stdout")
+    }
+    if opts.watch && (stdin || stdout) {
+        bail!("--watch can't be used with stdin or stdout")
+    }
+    if opts.dry_run && (stdin || opts.watch || opts.json) {
+        bail!("--dry-run can't be used with --watch, --json, or stdin")
+    }
+
+    if stdin || input.is_file() {
+        if let Some(output) = output
+            .as_deref()
+            .filter(|output| *output != Path::new("-") && !output.is_dir())
+        {
+            if !saver.format.matches(output) {
+                bail!(
+                    "the extension of {} doesn't match the format it would be saved in",
+                    output.display()
+                )
+            }
+        }
+        if opts.dry_run {
+            let name = Path::new(input.file_name().unwrap_or_default());
+            let outputs: Vec<_> = output
+                .iter()
+                .map(|output| {
+                    if output.is_dir() {
+                        output.join(saver.name(name))
+                    } else {
+                        output.to_owned()
+                    }
+                })
+                .chain(heatmap_path.cloned())
+                .collect();
+            let overwritten = print_plan(&input, &outputs, &canonical_inputs(&input))?;
+            eprintln!("1 to convert, {} to overwrite", overwritten);
+            return Ok(());
+        }
+        let convert = || {
+            let start = Instant::now();
+            let output = output.as_deref();
+            let saved = convert_file(
+                &input,
+                output,
+                heatmap_path,
+                conversion,
+                saver,
+                json.is_none(),
+            );
+            if let Some(json) = &json {
+                json.image(&input, saver, &saved, start.elapsed());
+                json.summary(&Summary {
+                    converted: saved.is_ok() as usize,
+                    failed: saved.is_err() as usize,
+                    duration: start.elapsed().as_secs_f64(),
+                    ..Summary::default()
+                });
+            }
+            saved.map(|_| ())
+        };
+        convert()?;
+        if opts.watch {
+            // watch the directory of the file, since editors often replace
+            // files instead of writing to them
+            let file = fs::canonicalize(&input)?;
+            let dir = file.parent().unwrap_or_else(|| Path::new("/"));
+            watch(dir, false, |changed, _| {
+                if changed.contains(&file) {
+                    if let Err(e) = convert() {
+                        eprintln!("error: {:#}", e)
+                    }
+                }
+            })?
+        }
+    } else if input.is_dir() && output.as_ref().is_none_or(|output| output.is_dir()) {
+        if let Some(dir) = heatmap_path.filter(|dir| !dir.is_dir()) {
+            bail!(
+                "{} must be a directory when converting a directory",
+                dir.display()
+            )
+        }
+        if opts.incremental && output.is_none() {
+            bail!("--incremental needs an OUTPUT directory")
+        }
+        if let Some(jobs) = opts.jobs {
+            set_jobs(jobs)?;
+        }
+        let root = fs::canonicalize(&input)?;
+        let recursive = opts.recursive;
+        // with --recursive, converting a directory into one of its
+        // subdirectories would convert the converted images on the next run
+        let skip = output
+            .iter()
+            .chain(heatmap_path)
+            .filter(|_| recursive)
+            .filter_map(|dir| fs::canonicalize(dir).ok())
+            .filter(|dir| dir != &root && dir.starts_with(&root))
+            .collect();
+        let batch = Batch {
+            input: &input,
+            output: output.as_deref(),
+            heatmap: heatmap_path,
+            conversion,
+            saver,
+            filter: Filter {
+                include: glob_set(&opts.include)?,
+                exclude: glob_set(&opts.exclude)?,
+                // accept `.png` as well as `png`
+                extensions: opts
+                    .extensions
+                    .iter()
+                    .map(|e| e.trim_start_matches('.').to_lowercase())
+                    .collect(),
+            },
+            recursive,
+            skip,
+            incremental: opts.incremental,
+            fail_fast: opts.fail_fast,
+            dry_run: opts.dry_run,
+            json,
+            memory: opts
+                .max_memory
+                .map(|mb| MemoryBudget::new(mb * 1024 * 1024)),
+            written: Mutex::default(),
+        };
+        batch.run(batch.files()?)?;
+
+        if opts.watch {
+            watch(&root, recursive, |changed, removed| {
+                // leave out files that aren't images, like the manifest, and
+                // the images that were just converted, in case they are saved
+                // in INPUT
+                let written = batch.written.lock().unwrap().clone();
+                let images: Vec<_> = changed
+                    .iter()
+                    .filter(|path| path.is_file() && !written.contains(*path))
+                    .filter(|path| !batch.skip.iter().any(|dir| path.starts_with(dir)))
+                    .filter_map(|path| path.strip_prefix(&root).ok())
+                    .filter(|path| is_image(path) && batch.filter.matches(path))
+                    .map(Path::to_owned)
+                    .collect();
+                // deleted images only matter when cleaning up their outputs
+                if !images.is_empty() || (removed && batch.incremental) {
+                    if let Err(e) = batch.run(images) {
+                        eprintln!("error: {:#}", e)
+                    }
+                }
+            })?
+        }
+    } else {
+        bail!("INPUT and OUTPUT must both be either a file or directory")
+    }
+    Ok(())
+}
+
+/// Convert a single image, or one read from stdin if `input` is `-`
+fn convert_file(
+    input: &Path,
+    output: Option<&Path>,
+    heatmap_path: Option<&PathBuf>,
+    conversion: &Conversion,
+    saver: &Saver,
+    print: bool,
+) -> Result<Saved> {
+    let converted = if input == Path::new("-") {
+        convert_stdin(conversion)?
+    } else {
+        convert_image(input, conversion)?
+    };
+    if print {
+        print_report(input, converted.report);
+    }
+    print_preview(input, converted.preview.as_deref())?;
+    let inputs = canonical_inputs(input);
+    if let (Some(path), Some(heatmap)) = (heatmap_path, &converted.heatmap) {
+        check_overwrite(path, &inputs)?;
+        heatmap.save(path)?;
+    }
+    let output = match output {
+        Some(output) if output == Path::new("-") => {
+            saver.write(&converted.img, converted.format, &conversion.palette)?;
+            Some(output.to_owned())
+        }
+        Some(output) => {
+            let path = if output.is_dir() {
+                output.join(saver.name(Path::new(&converted.name)))
+            } else {
+                output.to_owned()
+            };
+            saver.save(&converted.img, &path, &inputs, &conversion.palette)?;
+            Some(path)
+        }
+        None => None,
+    };
+    Ok(Saved::new(output, &converted))
+}
+
+/// Convert up to `jobs` images at once
+fn set_jobs(jobs: usize) -> Result<()> {
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(jobs)
+        .build_global()?;
+    Ok(())
+}
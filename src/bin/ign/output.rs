@@ -0,0 +1,320 @@
+use color_eyre::eyre::{bail, eyre, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use image::{
+    codecs::{
+        jpeg::JpegEncoder,
+        png::{CompressionType, FilterType as PngFilter, PngEncoder},
+    },
+    ColorType, DynamicImage, ImageFormat, RgbaImage,
+};
+use image_go_nord::{
+    ansi::{ansi_art, AnsiOptions},
+    metrics::Report,
+    palette::DynamicPalette,
+};
+
+/// The format converted images are saved in
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Format {
+    /// Use the format matching the extension of the output file
+    #[default]
+    Image,
+    Png,
+    Jpeg,
+    Gif,
+    Bmp,
+    Tiff,
+    Tga,
+    Ansi,
+}
+
+impl Format {
+    /// The image format to encode with, or `None` to pick it by extension
+    fn image_format(self) -> Option<ImageFormat> {
+        match self {
+            Format::Png => Some(ImageFormat::Png),
+            Format::Jpeg => Some(ImageFormat::Jpeg),
+            Format::Gif => Some(ImageFormat::Gif),
+            Format::Bmp => Some(ImageFormat::Bmp),
+            Format::Tiff => Some(ImageFormat::Tiff),
+            Format::Tga => Some(ImageFormat::Tga),
+            Format::Image | Format::Ansi => None,
+        }
+    }
+
+    /// The extension of files in this format, or `None` to keep the
+    /// extension of the input file
+    fn extension(self) -> Option<&'static str> {
+        match self {
+            Format::Image => None,
+            Format::Png => Some("png"),
+            Format::Jpeg => Some("jpg"),
+            Format::Gif => Some("gif"),
+            Format::Bmp => Some("bmp"),
+            Format::Tiff => Some("tiff"),
+            Format::Tga => Some("tga"),
+            Format::Ansi => Some("ans"),
+        }
+    }
+
+    /// Whether a file at `path` can be saved in this format, which it can't
+    /// if its extension is for another format
+    pub(crate) fn matches(self, path: &Path) -> bool {
+        let format = ImageFormat::from_path(path).ok();
+        match self {
+            Format::Image => true,
+            // ANSI art is text, so any extension but an image one will do
+            Format::Ansi => format.is_none(),
+            _ => format == self.image_format(),
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "image" => Ok(Format::Image),
+            "png" => Ok(Format::Png),
+            "jpeg" | "jpg" => Ok(Format::Jpeg),
+            "gif" => Ok(Format::Gif),
+            "bmp" => Ok(Format::Bmp),
+            "tiff" | "tif" => Ok(Format::Tiff),
+            "tga" => Ok(Format::Tga),
+            "ansi" => Ok(Format::Ansi),
+            "webp" => Err("saving WebP images isn't supported yet".to_owned()),
+            _ => Err(format!(
+                "unknown format '{}', expected one of: image, png, jpeg, gif, bmp, tiff, tga, ansi",
+                s
+            )),
+        }
+    }
+}
+
+/// How much PNG images are compressed
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum PngCompression {
+    #[default]
+    Fast,
+    Medium,
+    Best,
+}
+
+impl FromStr for PngCompression {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "fast" => Ok(PngCompression::Fast),
+            "medium" => Ok(PngCompression::Medium),
+            "best" => Ok(PngCompression::Best),
+            _ => Err(format!(
+                "unknown PNG compression '{}', expected one of: fast, medium, best",
+                s
+            )),
+        }
+    }
+}
+
+impl PngCompression {
+    /// The compression and filter of the PNG encoder. `Fast` matches what
+    /// `image` uses by default.
+    fn settings(self) -> (CompressionType, PngFilter) {
+        match self {
+            PngCompression::Fast => (CompressionType::Fast, PngFilter::Sub),
+            PngCompression::Medium => (CompressionType::Default, PngFilter::Sub),
+            PngCompression::Best => (CompressionType::Best, PngFilter::Paeth),
+        }
+    }
+}
+
+/// The name template used unless another one is given
+pub(crate) const DEFAULT_NAME_TEMPLATE: &str = "{stem}.{ext}";
+/// The JPEG quality used unless another one is given, which is also the
+/// default of `image`
+pub(crate) const DEFAULT_JPEG_QUALITY: u8 = 75;
+
+/// Names converted images and saves them in the output format
+#[derive(Clone, Debug)]
+pub(crate) struct Saver {
+    pub(crate) format: Format,
+    /// The name template, see `--name-template`
+    pub(crate) template: String,
+    /// What `{palette}` is replaced with in the name template
+    pub(crate) palette_name: String,
+    pub(crate) ansi: AnsiOptions,
+    pub(crate) jpeg_quality: u8,
+    pub(crate) png_compression: PngCompression,
+}
+
+impl Saver {
+    /// Check the name template and encoder options before converting anything
+    pub(crate) fn check(&self) -> Result<()> {
+        let name = self.render("", "");
+        if name.contains(['{', '}']) {
+            bail!(
+                "unknown placeholder in name template '{}', expected {{stem}}, {{ext}}, or {{palette}}",
+                self.template
+            )
+        }
+        if !(1..=100).contains(&self.jpeg_quality) {
+            bail!("JPEG quality must be between 1 and 100")
+        }
+        Ok(())
+    }
+
+    fn render(&self, stem: &str, ext: &str) -> String {
+        self.template
+            .replace("{stem}", stem)
+            .replace("{ext}", ext)
+            .replace("{palette}", &self.palette_name)
+    }
+
+    /// The path a converted image is saved to, relative to the output
+    /// directory, given the path of its input relative to the input directory
+    pub(crate) fn name(&self, input: &Path) -> PathBuf {
+        let stem = input.file_stem().unwrap_or_default().to_string_lossy();
+        let ext = match self.format.extension() {
+            Some(ext) => ext.into(),
+            None => input.extension().unwrap_or_default().to_string_lossy(),
+        };
+        input.with_file_name(self.render(&stem, &ext))
+    }
+
+    /// Save a converted image, refusing to replace any of the `inputs`, which
+    /// are the canonical paths of the images being converted
+    pub(crate) fn save(
+        &self,
+        img: &RgbaImage,
+        path: &Path,
+        inputs: &HashSet<PathBuf>,
+        palette: &DynamicPalette,
+    ) -> Result<()> {
+        check_overwrite(path, inputs)?;
+        if self.format == Format::Ansi {
+            fs::write(path, ansi_art(img, palette, self.ansi))?;
+            return Ok(());
+        }
+
+        let format = match self.format.image_format() {
+            Some(format) => format,
+            None => ImageFormat::from_path(path)?,
+        };
+        match format {
+            // TIFF needs to seek, so it can't be encoded to any writer
+            ImageFormat::Tiff => img.save_with_format(path, format)?,
+            format => self.encode(img, format, &mut BufWriter::new(File::create(path)?))?,
+        }
+        Ok(())
+    }
+
+    /// Write a converted image to stdout, in the format of its input if no
+    /// other format is given
+    pub(crate) fn write(
+        &self,
+        img: &RgbaImage,
+        input: Option<ImageFormat>,
+        palette: &DynamicPalette,
+    ) -> Result<()> {
+        let stdout = io::stdout();
+        let mut stdout = stdout.lock();
+        if self.format == Format::Ansi {
+            stdout.write_all(ansi_art(img, palette, self.ansi).as_bytes())?;
+        } else {
+            let format = self
+                .format
+                .image_format()
+                .or(input)
+                .ok_or_else(|| eyre!("use --format to choose the format written to stdout"))?;
+            self.encode(img, format, &mut stdout)?;
+        }
+        stdout.flush()?;
+        Ok(())
+    }
+
+    /// Encode an image with the encoder options
+    fn encode(&self, img: &RgbaImage, format: ImageFormat, w: &mut impl Write) -> Result<()> {
+        let (width, height) = img.dimensions();
+        match format {
+            ImageFormat::Png => {
+                let (compression, filter) = self.png_compression.settings();
+                PngEncoder::new_with_quality(w, compression, filter).encode(
+                    img,
+                    width,
+                    height,
+                    ColorType::Rgba8,
+                )?
+            }
+            ImageFormat::Jpeg => JpegEncoder::new_with_quality(w, self.jpeg_quality).encode(
+                img,
+                width,
+                height,
+                ColorType::Rgba8,
+            )?,
+            ImageFormat::Tiff => bail!("TIFF images can only be saved to files"),
+            format => DynamicImage::ImageRgba8(img.clone()).write_to(w, format)?,
+        }
+        Ok(())
+    }
+}
+
+/// The canonical path of a single input, or none for stdin
+pub(crate) fn canonical_inputs(input: &Path) -> HashSet<PathBuf> {
+    if input == Path::new("-") {
+        return HashSet::new();
+    }
+    fs::canonicalize(input).into_iter().collect()
+}
+
+/// Whether saving to `path` would overwrite one of the `inputs`, which are
+/// canonical paths
+pub(crate) fn overwrites_input(path: &Path, inputs: &HashSet<PathBuf>) -> Result<bool> {
+    Ok(path.exists() && inputs.contains(&fs::canonicalize(path)?))
+}
+
+/// Refuse to save to `path` if it would overwrite one of the `inputs`
+pub(crate) fn check_overwrite(path: &Path, inputs: &HashSet<PathBuf>) -> Result<()> {
+    if overwrites_input(path, inputs)? {
+        bail!(
+            "refusing to overwrite {}, which is one of the images being converted; use --name-template to save it under another name",
+            path.display()
+        )
+    }
+    Ok(())
+}
+
+/// Create the parent directories of a path that is about to be written
+pub(crate) fn create_parent(path: &Path) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    Ok(())
+}
+
+pub(crate) fn print_preview(path: &Path, preview: Option<&str>) -> Result<()> {
+    if let Some(preview) = preview {
+        // lock stdout so previews of images converted in parallel don't mix
+        let stdout = io::stdout();
+        let mut stdout = stdout.lock();
+        writeln!(stdout, "{}:", path.display())?;
+        stdout.write_all(preview.as_bytes())?;
+    }
+    Ok(())
+}
+
+pub(crate) fn print_report(path: &Path, report: Option<Report>) {
+    if let Some(report) = report {
+        println!("{}: {}", path.display(), report)
+    }
+}
@@ -0,0 +1,237 @@
+use color_eyre::eyre::Result;
+use serde::Serialize;
+use serde_json::json;
+use std::{
+    fmt,
+    io::{self, IsTerminal},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use image_go_nord::metrics::Report;
+
+use crate::{
+    conversion::{Algorithm, Conversion, ConvertedImage, Selection},
+    output::{Format, Saver},
+};
+
+/// What was done with an image that was converted, for `--json`
+pub(crate) struct Saved {
+    /// Where the converted image was saved, if it was
+    output: Option<PathBuf>,
+    dimensions: (u32, u32),
+    report: Option<Report>,
+}
+
+impl Saved {
+    pub(crate) fn new(output: Option<PathBuf>, converted: &ConvertedImage) -> Self {
+        Saved {
+            output,
+            dimensions: converted.img.dimensions(),
+            report: converted.report,
+        }
+    }
+}
+
+/// The numbers of images in a batch by what was done with them
+#[derive(Debug, Default, Serialize)]
+#[serde(tag = "type", rename = "summary")]
+pub(crate) struct Summary {
+    pub(crate) converted: usize,
+    /// Images that were skipped because they didn't change, with
+    /// `--incremental`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) unchanged: Option<usize>,
+    /// Converted images that were removed because their input was, with
+    /// `--incremental`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) removed: Option<usize>,
+    /// Files that were skipped because they aren't images
+    pub(crate) skipped: usize,
+    /// Images that were left out by `--include`, `--exclude`, or
+    /// `--extensions`
+    pub(crate) filtered: usize,
+    pub(crate) failed: usize,
+    /// How long the batch took, in seconds
+    pub(crate) duration: f64,
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} converted", self.converted)?;
+        if let (Some(unchanged), Some(removed)) = (self.unchanged, self.removed) {
+            write!(f, ", {} unchanged, {} removed", unchanged, removed)?;
+        }
+        write!(f, ", {} skipped", self.skipped)?;
+        // most batches aren't filtered, so leave it out when it's zero
+        if self.filtered > 0 {
+            write!(f, ", {} filtered", self.filtered)?;
+        }
+        write!(f, ", {} failed", self.failed)
+    }
+}
+
+/// Prints the records of `--json`, each a JSON object on a line of stdout
+pub(crate) struct JsonRecords {
+    /// The settings images are converted with, named like the keys of config
+    /// files
+    options: serde_json::Value,
+}
+
+impl JsonRecords {
+    /// Records of images converted with `conversion` and saved with `saver`,
+    /// along with the paths of the LUT and mask they were read from
+    pub(crate) fn new(
+        conversion: &Conversion,
+        saver: &Saver,
+        lut: Option<&Path>,
+        mask: Option<&Path>,
+    ) -> Self {
+        let mut options = match &conversion.algorithm {
+            Algorithm::Palette(options) => json!({
+                "mode": options.mode,
+                "blur": options.blur,
+                "quantize": options.quantize,
+                "no-dither": !options.dither,
+                "lut": lut,
+                "lut-interpolation": options.lut_interpolation,
+                "lut-only": options.skip_palette,
+            }),
+            Algorithm::Gradient(order, interpolation) => json!({
+                "gradient": interpolation,
+                "order": order,
+            }),
+        };
+        match &conversion.selection {
+            Selection::All => {}
+            Selection::Region(region) => {
+                options["region"] = json!([region.x, region.y, region.width, region.height]);
+                options["feather"] = json!(conversion.feather);
+            }
+            Selection::Mask(_) => {
+                options["mask"] = json!(mask);
+                options["feather"] = json!(conversion.feather);
+            }
+        }
+        options["format"] = json!(saver.format);
+        options["name-template"] = json!(saver.template);
+        options["jpeg-quality"] = json!(saver.jpeg_quality);
+        options["png-compression"] = json!(saver.png_compression);
+        if saver.format == Format::Ansi {
+            options["columns"] = json!(saver.ansi.columns);
+            options["ansi-colors"] = json!(saver.ansi.colors);
+            options["characters"] = json!(saver.ansi.characters);
+        }
+        JsonRecords { options }
+    }
+
+    /// Print the record of an image that was converted, or failed to
+    pub(crate) fn image(
+        &self,
+        input: &Path,
+        saver: &Saver,
+        saved: &Result<Saved>,
+        duration: Duration,
+    ) {
+        let mut record = json!({
+            "type": "image",
+            "input": input,
+            "palette": saver.palette_name,
+            "options": self.options,
+            "duration": duration.as_secs_f64(),
+        });
+        match saved {
+            Ok(saved) => {
+                let (width, height) = saved.dimensions;
+                record["status"] = json!("converted");
+                record["output"] = json!(saved.output);
+                record["width"] = json!(width);
+                record["height"] = json!(height);
+                if let Some(report) = saved.report {
+                    record["metrics"] = report_json(report);
+                }
+            }
+            Err(e) => {
+                record["status"] = json!("failed");
+                record["error"] = json!(format!("{:#}", e));
+            }
+        }
+        println!("{}", record);
+    }
+
+    pub(crate) fn summary(&self, summary: &Summary) {
+        println!("{}", json!(summary));
+    }
+}
+
+fn report_json(report: Report) -> serde_json::Value {
+    let delta_e = report.delta_e;
+    json!({
+        "delta_e": {
+            "mean": delta_e.mean,
+            "median": delta_e.median,
+            "p90": delta_e.p90,
+            "p95": delta_e.p95,
+            "p99": delta_e.p99,
+            "max": delta_e.max,
+        },
+        "psnr": report.psnr,
+        "ssim": report.ssim,
+    })
+}
+
+/// A progress bar with an estimate of the time left, drawn on stderr while a
+/// directory is converted
+pub(crate) struct ProgressBar {
+    total: usize,
+    start: Instant,
+    /// The number of images that are done, locked while drawing the bar
+    done: Mutex<usize>,
+}
+
+impl ProgressBar {
+    const WIDTH: usize = 30;
+
+    /// A progress bar for `total` images, or `None` if stderr isn't a terminal
+    pub(crate) fn new(total: usize) -> Option<Self> {
+        let progress = (total > 0 && io::stderr().is_terminal()).then(|| ProgressBar {
+            total,
+            start: Instant::now(),
+            done: Mutex::new(0),
+        })?;
+        progress.draw(0);
+        Some(progress)
+    }
+
+    /// Count one more image as done
+    pub(crate) fn tick(&self) {
+        let mut done = self.done.lock().unwrap();
+        *done += 1;
+        self.draw(*done);
+    }
+
+    pub(crate) fn clear(&self) {
+        eprint!("\r\x1b[K");
+    }
+
+    fn draw(&self, done: usize) {
+        let filled = Self::WIDTH * done / self.total;
+        let eta = if done > 0 && done < self.total {
+            let left =
+                self.start.elapsed().as_secs_f64() * (self.total - done) as f64 / done as f64;
+            let left = left.round() as u64;
+            format!(" ETA {}:{:02}", left / 60, left % 60)
+        } else {
+            String::new()
+        };
+        eprint!(
+            "\r\x1b[K[{}{}] {}/{}{}",
+            "#".repeat(filled),
+            ".".repeat(Self::WIDTH - filled),
+            done,
+            self.total,
+            eta
+        );
+    }
+}
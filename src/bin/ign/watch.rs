@@ -0,0 +1,56 @@
+use color_eyre::eyre::Result;
+use notify::{DebouncedEvent, RecursiveMode, Watcher};
+use std::{
+    collections::BTreeSet,
+    iter,
+    path::{Path, PathBuf},
+    sync::mpsc,
+    time::Duration,
+};
+
+/// How long files have to stay unchanged before `--watch` converts them
+const WATCH_DELAY: Duration = Duration::from_millis(500);
+
+/// Watch a directory until an error occurs, calling `on_change` with the
+/// files that were created or changed, and whether any were removed
+pub(crate) fn watch(
+    dir: &Path,
+    recursive: bool,
+    mut on_change: impl FnMut(BTreeSet<PathBuf>, bool),
+) -> Result<()> {
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::watcher(tx, WATCH_DELAY)?;
+    let mode = if recursive {
+        RecursiveMode::Recursive
+    } else {
+        RecursiveMode::NonRecursive
+    };
+    watcher.watch(dir, mode)?;
+    eprintln!("watching {} for changes", dir.display());
+
+    loop {
+        let mut changed = BTreeSet::new();
+        let mut removed = false;
+        // wait for a change, then handle the ones that came with it together
+        for event in iter::once(rx.recv()?).chain(rx.try_iter()) {
+            match event {
+                DebouncedEvent::Create(path) | DebouncedEvent::Write(path) => {
+                    changed.insert(path);
+                }
+                DebouncedEvent::Rename(_, path) => {
+                    changed.insert(path);
+                    removed = true;
+                }
+                DebouncedEvent::Remove(_) => removed = true,
+                // errors like a file that can't be watched shouldn't stop
+                // watching the others
+                DebouncedEvent::Error(e, Some(path)) => {
+                    eprintln!("error: {}: {}", path.display(), e)
+                }
+                DebouncedEvent::Error(e, None) => eprintln!("error: {}", e),
+                _ => {}
+            }
+        }
+        on_change(changed, removed);
+    }
+}
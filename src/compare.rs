@@ -0,0 +1,203 @@
+use crate::font::{draw_text, text_size};
+use image::{
+    imageops::{overlay, resize, ColorMap, FilterType::Triangle},
+    Rgba, RgbaImage,
+};
+use std::str::FromStr;
+
+/// Background color of labels (nord0)
+const LABEL_BACKGROUND: Rgba<u8> = Rgba([0x2E, 0x34, 0x40, 0xFF]);
+/// Text color of labels (nord6)
+const LABEL_TEXT: Rgba<u8> = Rgba([0xEC, 0xEF, 0xF4, 0xFF]);
+
+/// How the original and converted images are arranged in a comparison.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Layout {
+    /// Place the images next to each other, original on the left.
+    #[default]
+    SideBySide,
+    /// Place the images on top of each other, original on top.
+    Stacked,
+    /// Show the left half of the original and the right half of the converted
+    /// image.
+    Split,
+    /// Split the image along the diagonal from the bottom left to the top
+    /// right, with the original in the top left.
+    Diagonal,
+}
+
+impl FromStr for Layout {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "side-by-side" => Ok(Layout::SideBySide),
+            "stacked" => Ok(Layout::Stacked),
+            "split" => Ok(Layout::Split),
+            "diagonal" => Ok(Layout::Diagonal),
+            _ => Err(format!(
+                "unknown layout '{}', expected one of: side-by-side, stacked, split, diagonal",
+                s
+            )),
+        }
+    }
+}
+
+/// These options control how `compare` renders a comparison image.
+///
+/// Like [`Options`](crate::Options), `CompareOptions` implements
+/// [`std::default::Default`], so you can override only the fields you need.
+///
+/// ```
+/// # use image_go_nord::compare::{CompareOptions, Layout};
+/// let options = CompareOptions {
+///     layout: Layout::Diagonal,
+///     swatches: true,
+///     ..Default::default()
+/// };
+/// assert_eq!(options.labels, None);
+/// ```
+#[cfg_attr(feature = "serde", derive(serde::Deserialize))]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CompareOptions {
+    /// How the original and converted images are arranged.
+    pub layout: Layout,
+    /// Add a strip with a swatch of each palette color to the bottom of the
+    /// image.
+    pub swatches: bool,
+    /// Labels drawn in the top left corner of the original and converted
+    /// images, in that order. Only ASCII letters, digits, and some punctuation
+    /// can be drawn.
+    pub labels: Option<[String; 2]>,
+}
+
+/// Render an original and a converted image into a single comparison image.
+///
+/// The converted image is stretched to the size of the original if their
+/// dimensions differ. See [`CompareOptions`] for the available layouts and
+/// decorations.
+///
+/// ```
+/// # use image::{Rgba, RgbaImage};
+/// # use image_go_nord::{compare::{compare, CompareOptions, Layout}, convert, NORD};
+/// let original = RgbaImage::from_pixel(8, 6, Rgba([255, 0, 0, 255]));
+/// let converted = convert(&original, Default::default(), &NORD);
+///
+/// let side_by_side = compare(&original, &converted, &NORD, Default::default());
+/// assert_eq!(side_by_side.dimensions(), (16, 6));
+///
+/// let options = CompareOptions { layout: Layout::Split, ..Default::default() };
+/// let split = compare(&original, &converted, &NORD, options);
+/// assert_eq!(split.dimensions(), (8, 6));
+/// ```
+pub fn compare(
+    original: &RgbaImage,
+    converted: &RgbaImage,
+    palette: &impl ColorMap<Color = Rgba<u8>>,
+    opt: CompareOptions,
+) -> RgbaImage {
+    let (w, h) = original.dimensions();
+    let resized;
+    let converted = if converted.dimensions() != (w, h) {
+        resized = resize(converted, w, h, Triangle);
+        &resized
+    } else {
+        converted
+    };
+
+    // the label scale grows with the image so labels stay legible
+    let scale = (w.min(h) / 200).max(1);
+    let mut img = match opt.layout {
+        Layout::SideBySide => {
+            let mut img = RgbaImage::new(w * 2, h);
+            overlay(&mut img, original, 0, 0);
+            overlay(&mut img, converted, w, 0);
+            img
+        }
+        Layout::Stacked => {
+            let mut img = RgbaImage::new(w, h * 2);
+            overlay(&mut img, original, 0, 0);
+            overlay(&mut img, converted, 0, h);
+            img
+        }
+        Layout::Split => RgbaImage::from_fn(w, h, |x, y| {
+            if x < w / 2 {
+                *original.get_pixel(x, y)
+            } else {
+                *converted.get_pixel(x, y)
+            }
+        }),
+        Layout::Diagonal => RgbaImage::from_fn(w, h, |x, y| {
+            // above the line from (0, h) to (w, 0)
+            if (x as u64 * h as u64) + (y as u64 * w as u64) < w as u64 * h as u64 {
+                *original.get_pixel(x, y)
+            } else {
+                *converted.get_pixel(x, y)
+            }
+        }),
+    };
+
+    if let Some([before, after]) = &opt.labels {
+        draw_label(&mut img, before, (0, 0), scale);
+        let position = match opt.layout {
+            Layout::SideBySide => (w, 0),
+            Layout::Stacked => (0, h),
+            Layout::Split => (w / 2, 0),
+            // the top left is taken by the original, so use the bottom right
+            Layout::Diagonal => {
+                let (label_w, label_h) = label_size(after, scale);
+                (w.saturating_sub(label_w), h.saturating_sub(label_h))
+            }
+        };
+        draw_label(&mut img, after, position, scale);
+    }
+
+    if opt.swatches {
+        img = add_swatches(&img, palette);
+    }
+    img
+}
+
+/// The size of a label including its background and margin
+fn label_size(label: &str, scale: u32) -> (u32, u32) {
+    let (text_w, text_h) = text_size(label, scale);
+    (text_w + 8 * scale, text_h + 8 * scale)
+}
+
+/// Draw a label on a dark background with a small margin
+fn draw_label(img: &mut RgbaImage, label: &str, (x, y): (u32, u32), scale: u32) {
+    let (text_w, text_h) = text_size(label, scale);
+    let padding = 2 * scale;
+    let background =
+        RgbaImage::from_pixel(text_w + 2 * padding, text_h + 2 * padding, LABEL_BACKGROUND);
+    overlay(img, &background, x + padding, y + padding);
+    draw_text(
+        img,
+        label,
+        (x + 2 * padding, y + 2 * padding),
+        scale,
+        LABEL_TEXT,
+    );
+}
+
+/// Append a strip with a swatch of each palette color to the bottom of an image
+fn add_swatches(img: &RgbaImage, palette: &impl ColorMap<Color = Rgba<u8>>) -> RgbaImage {
+    let colors: Vec<_> = (0..).map_while(|i| palette.lookup(i)).collect();
+    if colors.is_empty() {
+        return img.clone();
+    }
+
+    let (w, h) = img.dimensions();
+    let strip_h = (h / 12).max(8);
+    let mut result = RgbaImage::from_pixel(w, h + strip_h, LABEL_BACKGROUND);
+    overlay(&mut result, img, 0, 0);
+    for x in 0..w {
+        let color = colors[(x as usize * colors.len()) / w as usize];
+        for y in h..h + strip_h {
+            result.put_pixel(x, y, color);
+        }
+    }
+    result
+}
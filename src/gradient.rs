@@ -0,0 +1,128 @@
+use crate::utils::{lab_to_rgb, rgb_to_lab};
+use image::{imageops::ColorMap, Pixel, Rgba, RgbaImage};
+use std::str::FromStr;
+
+/// How the colors between two palette entries of a gradient are filled in.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Interpolation {
+    /// Blend smoothly between neighboring palette entries.
+    #[default]
+    Smooth,
+    /// Split the gradient into equal bands, one for each palette entry.
+    Stepped,
+}
+
+impl FromStr for Interpolation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "smooth" => Ok(Interpolation::Smooth),
+            "stepped" => Ok(Interpolation::Stepped),
+            _ => Err(format!(
+                "unknown interpolation '{}', expected one of: smooth, stepped",
+                s
+            )),
+        }
+    }
+}
+
+/// The order in which palette entries are laid out along a gradient, from the
+/// darkest pixels to the lightest.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Order {
+    /// Use the order the entries appear in the palette. Build a palette with
+    /// the entries in the desired order to get an explicit ramp.
+    Palette,
+    /// Sort the entries from darkest to lightest.
+    #[default]
+    Luminance,
+}
+
+impl FromStr for Order {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "palette" => Ok(Order::Palette),
+            "luminance" => Ok(Order::Luminance),
+            _ => Err(format!(
+                "unknown order '{}', expected one of: palette, luminance",
+                s
+            )),
+        }
+    }
+}
+
+/// Map the lightness of each pixel onto a palette treated as a gradient.
+///
+/// Instead of matching each pixel to its nearest palette color, the palette is
+/// laid out as a ramp (ordered according to `order`) and each pixel is
+/// replaced by the color at the position of its lightness along the ramp. This
+/// gives duotone-style results, like mapping shadows to Polar Night and
+/// highlights to Snow Storm. The alpha channel is left untouched.
+///
+/// ```
+/// # use image::{Rgba, RgbaImage};
+/// # use image_go_nord::{gradient::{gradient_map, Interpolation, Order}, palette::StaticPalette};
+/// let black_and_white = StaticPalette::from_rgb_hex([0xFFFFFF, 0x000000]);
+/// let img = RgbaImage::from_pixel(1, 1, Rgba([0, 0, 0, 255]));
+///
+/// // sorting by luminance puts black at the start of the ramp
+/// let sorted = gradient_map(&img, &black_and_white, Order::Luminance, Interpolation::Smooth);
+/// assert_eq!(sorted.get_pixel(0, 0), &Rgba([0, 0, 0, 255]));
+///
+/// // while the palette order puts white there
+/// let inverted = gradient_map(&img, &black_and_white, Order::Palette, Interpolation::Smooth);
+/// assert_eq!(inverted.get_pixel(0, 0), &Rgba([255, 255, 255, 255]));
+/// ```
+///
+/// # Panics
+///
+/// The palette must support [`ColorMap::lookup`] and have at least one entry.
+pub fn gradient_map(
+    img: &RgbaImage,
+    palette: &impl ColorMap<Color = Rgba<u8>>,
+    order: Order,
+    interpolation: Interpolation,
+) -> RgbaImage {
+    assert!(
+        palette.has_lookup(),
+        "gradient palettes must support lookup"
+    );
+    let mut stops: Vec<[f32; 3]> = (0..)
+        .map_while(|i| palette.lookup(i))
+        .map(|c| rgb_to_lab(c.channels()))
+        .collect();
+    assert!(!stops.is_empty(), "gradient palettes must not be empty");
+    if order == Order::Luminance {
+        stops.sort_by(|a, b| a[0].partial_cmp(&b[0]).unwrap());
+    }
+
+    let mut img = img.clone();
+    for pixel in img.pixels_mut() {
+        // lightness of the pixel along the ramp, between 0 and 1
+        let t = (rgb_to_lab(pixel.channels())[0] / 100.).clamp(0., 1.);
+        let color = match interpolation {
+            Interpolation::Stepped => {
+                let i = (t * stops.len() as f32) as usize;
+                lab_to_rgb(stops[i.min(stops.len() - 1)])
+            }
+            Interpolation::Smooth if stops.len() == 1 => lab_to_rgb(stops[0]),
+            Interpolation::Smooth => {
+                let pos = t * (stops.len() - 1) as f32;
+                let i = (pos as usize).min(stops.len() - 2);
+                let frac = pos - i as f32;
+                let (a, b) = (stops[i], stops[i + 1]);
+                lab_to_rgb([0, 1, 2].map(|c| a[c] + (b[c] - a[c]) * frac))
+            }
+        };
+        // don't overwrite the alpha channel
+        pixel.channels_mut()[..3].copy_from_slice(&color);
+    }
+    img
+}
@@ -0,0 +1,166 @@
+use crate::{
+    metrics::ciede2000,
+    utils::{delta, rgb_to_lab},
+};
+use image::{Rgba, RgbaImage};
+use std::str::FromStr;
+
+/// The ΔE at which [`Metric::Ciede2000`] heatmaps reach the end of their
+/// colormap
+const MAX_DELTA_E: f64 = 50.;
+
+/// How the distance between an original and converted pixel is measured.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Metric {
+    /// Euclidean distance in RGB, using [`delta`]. The end of the colormap is
+    /// the distance between black and white.
+    Euclidean,
+    /// Perceptual CIEDE2000 color difference. The end of the colormap is a ΔE
+    /// of 50, and anything above it is clamped.
+    #[default]
+    Ciede2000,
+}
+
+impl FromStr for Metric {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "euclidean" => Ok(Metric::Euclidean),
+            "ciede2000" | "delta-e" => Ok(Metric::Ciede2000),
+            _ => Err(format!(
+                "unknown metric '{}', expected one of: euclidean, ciede2000",
+                s
+            )),
+        }
+    }
+}
+
+/// The colors used to draw a heatmap, from no error to the largest error.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Colormap {
+    /// Black to white
+    Grayscale,
+    /// Perceptually uniform purple, teal, and yellow
+    #[default]
+    Viridis,
+    /// Perceptually uniform black, red, and yellow
+    Inferno,
+    /// Nord colors: Polar Night, Frost, yellow, and red
+    Nord,
+}
+
+impl Colormap {
+    fn stops(self) -> &'static [[u8; 3]] {
+        match self {
+            Colormap::Grayscale => &[[0, 0, 0], [255, 255, 255]],
+            Colormap::Viridis => &[
+                [0x44, 0x01, 0x54],
+                [0x3B, 0x52, 0x8B],
+                [0x21, 0x91, 0x8C],
+                [0x5E, 0xC9, 0x62],
+                [0xFD, 0xE7, 0x25],
+            ],
+            Colormap::Inferno => &[
+                [0x00, 0x00, 0x04],
+                [0x57, 0x10, 0x6E],
+                [0xBC, 0x37, 0x54],
+                [0xF9, 0x8E, 0x09],
+                [0xFC, 0xFF, 0xA4],
+            ],
+            Colormap::Nord => &[
+                [0x2E, 0x34, 0x40],
+                [0x5E, 0x81, 0xAC],
+                [0x88, 0xC0, 0xD0],
+                [0xEB, 0xCB, 0x8B],
+                [0xBF, 0x61, 0x6A],
+            ],
+        }
+    }
+
+    /// The color for a value between 0 and 1. Values outside of that range
+    /// are clamped.
+    ///
+    /// ```
+    /// # use image::Rgba;
+    /// # use image_go_nord::heatmap::Colormap;
+    /// assert_eq!(Colormap::Grayscale.color(0.), Rgba([0, 0, 0, 255]));
+    /// assert_eq!(Colormap::Grayscale.color(2.), Rgba([255, 255, 255, 255]));
+    /// ```
+    pub fn color(self, t: f64) -> Rgba<u8> {
+        let stops = self.stops();
+        let pos = t.clamp(0., 1.) * (stops.len() - 1) as f64;
+        let i = (pos as usize).min(stops.len() - 2);
+        let frac = pos - i as f64;
+        let (a, b) = (stops[i], stops[i + 1]);
+        let [r, g, b] =
+            [0, 1, 2].map(|c| (a[c] as f64 + (b[c] as f64 - a[c] as f64) * frac).round() as u8);
+        Rgba([r, g, b, 255])
+    }
+}
+
+impl FromStr for Colormap {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "grayscale" | "greyscale" => Ok(Colormap::Grayscale),
+            "viridis" => Ok(Colormap::Viridis),
+            "inferno" => Ok(Colormap::Inferno),
+            "nord" => Ok(Colormap::Nord),
+            _ => Err(format!(
+                "unknown colormap '{}', expected one of: grayscale, viridis, inferno, nord",
+                s
+            )),
+        }
+    }
+}
+
+/// Render the per-pixel distance between an original and converted image as a
+/// heatmap, showing where the palette fails to represent the original.
+///
+/// Distances are scaled to a fixed range for each [`Metric`], so heatmaps of
+/// different images can be compared with each other. The heatmap is fully
+/// opaque.
+///
+/// ```
+/// # use image::{Rgba, RgbaImage};
+/// # use image_go_nord::heatmap::{heatmap, Colormap, Metric};
+/// let original = RgbaImage::from_pixel(2, 1, Rgba([0, 0, 0, 255]));
+/// let mut converted = original.clone();
+/// converted.put_pixel(1, 0, Rgba([255, 255, 255, 255]));
+///
+/// let map = heatmap(&original, &converted, Metric::Euclidean, Colormap::Grayscale);
+/// assert_eq!(map.get_pixel(0, 0), &Rgba([0, 0, 0, 255]));
+/// assert_eq!(map.get_pixel(1, 0), &Rgba([255, 255, 255, 255]));
+/// ```
+///
+/// # Panics
+///
+/// Both images must have the same dimensions.
+pub fn heatmap(
+    original: &RgbaImage,
+    converted: &RgbaImage,
+    metric: Metric,
+    colormap: Colormap,
+) -> RgbaImage {
+    assert_eq!(
+        original.dimensions(),
+        converted.dimensions(),
+        "images must have the same dimensions"
+    );
+    let max_euclidean = (delta(&[0; 3], &[255; 3]) as f64).sqrt();
+    let (w, h) = original.dimensions();
+    RgbaImage::from_fn(w, h, |x, y| {
+        let (a, b) = (original.get_pixel(x, y), converted.get_pixel(x, y));
+        let t = match metric {
+            Metric::Euclidean => (delta(&a.0[..3], &b.0[..3]) as f64).sqrt() / max_euclidean,
+            Metric::Ciede2000 => ciede2000(rgb_to_lab(&a.0), rgb_to_lab(&b.0)) / MAX_DELTA_E,
+        };
+        colormap.color(t)
+    })
+}
@@ -1,11 +1,28 @@
+pub mod ansi;
+pub mod assignment;
+pub mod batch;
+pub mod compare;
+mod font;
+pub mod gradient;
+pub mod heatmap;
+pub mod lut;
+pub mod metrics;
 pub mod palette;
+pub mod pipeline;
+pub mod presets;
+#[cfg(feature = "preview")]
+pub mod preview;
+pub mod progress;
+pub mod region;
+pub mod stats;
 pub mod utils;
 
-use color_quant::NeuQuant;
-use image::{
-    imageops::{blur, dither, resize, ColorMap, FilterType::Triangle},
-    Rgba, RgbaImage,
-};
+use image::{imageops::ColorMap, Rgba, RgbaImage};
+
+use lut::Lut;
+use pipeline::Pipeline;
+use progress::{Cancelled, Hooks};
+use std::str::FromStr;
 
 pub use palette::palettes::*;
 
@@ -20,24 +37,31 @@ static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
 ///
 /// `Options` implements [`std::default::Default`], so you can use struct
 /// builder syntax to easily make an `Options` struct that "overrides" the
-/// default struct.
+/// default struct. Likewise, fields that are missing when deserializing take
+/// their default values.
 ///
-/// ```ignore
+/// ```
+/// # #![allow(deprecated)]
+/// # use image_go_nord::{lut::Interpolation, Mode, Options};
 /// let options = Options {
-///     resize: [1920, 1080],
+///     quantize: 10,
 ///     blur: 0.4,
 ///     ..Default::default()
 /// };
 /// assert_eq!(options, Options {
-///     resize: [1920, 1080],
-///     quantize: 0,
-///     avg: [0, 0],
-///     transparency_tolerance: 0,
+///     resize: 0,
+///     quantize: 10,
 ///     blur: 0.4,
+///     mode: Mode::Nearest,
+///     lut: None,
+///     lut_interpolation: Interpolation::Tetrahedral,
+///     skip_palette: false,
+///     dither: true,
 /// })
 /// ```
 #[cfg_attr(feature = "ffi", repr(C))]
 #[cfg_attr(feature = "serde", derive(serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
 #[derive(Clone, Debug, PartialEq)]
 pub struct Options {
     /// Resize image by a certain factor before performing other processing. The
@@ -60,6 +84,67 @@ pub struct Options {
     ///
     /// `0.0` means don't blur.
     pub blur: f32,
+    /// How pixels are recolored once they have been matched to a palette
+    /// color. See [`Mode`] for the available modes.
+    pub mode: Mode,
+    /// Apply a 3D LUT, like a hand-tuned look, to the image before it is
+    /// matched to the palette. See [`Lut`].
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub lut: Option<Lut>,
+    /// How colors between the points of `lut` are interpolated.
+    pub lut_interpolation: lut::Interpolation,
+    /// Don't match the image to the palette, so only `lut` and the other
+    /// processing steps are applied.
+    pub skip_palette: bool,
+    /// Spread the difference between each pixel and its palette color to the
+    /// pixels around it (Floyd–Steinberg dithering), which keeps gradients
+    /// smooth. Disable it to keep flat areas and hard edges, like in pixel art.
+    ///
+    /// Dithering is enabled by default.
+    pub dither: bool,
+}
+
+/// Palette mapping modes used by `convert`.
+///
+/// Snapping every pixel to the nearest palette color can destroy the lightness
+/// structure of an image, especially with palettes like Nord that are mostly
+/// mid-luminance. The other modes only take part of the matched color.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Mode {
+    /// Replace each pixel with the closest palette color.
+    #[default]
+    Nearest,
+    /// Keep the luminance of each pixel and only take the hue and chroma of
+    /// the closest palette color. See [`PreserveLuminance`](palette::PreserveLuminance).
+    Luminance,
+    /// Match pixels to palette colors by hue alone and only replace their hue,
+    /// keeping their saturation and lightness. See [`HueOnly`](palette::HueOnly).
+    Hue,
+    /// Cluster the colors of the image and assign the clusters to palette
+    /// colors so that every palette color is used. See [`Assignment`](assignment::Assignment).
+    ///
+    /// Color maps that don't support [`ColorMap::lookup`] fall back to
+    /// [`Mode::Nearest`], since their colors can't be listed.
+    Balanced,
+}
+
+impl FromStr for Mode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "nearest" => Ok(Mode::Nearest),
+            "luminance" => Ok(Mode::Luminance),
+            "hue" => Ok(Mode::Hue),
+            "balanced" => Ok(Mode::Balanced),
+            _ => Err(format!(
+                "unknown mode '{}', expected one of: nearest, luminance, hue, balanced",
+                s
+            )),
+        }
+    }
 }
 
 impl Default for Options {
@@ -69,46 +154,34 @@ impl Default for Options {
             blur: 0.,
             resize: 0,
             quantize: 0,
+            mode: Mode::default(),
+            lut: None,
+            lut_interpolation: lut::Interpolation::default(),
+            skip_palette: false,
+            dither: true,
         }
     }
 }
 
 // TODO: make generic over different image types
-#[allow(deprecated)]
+/// Convert an image to a palette using the preset pipeline described by
+/// `opt`. See [`Pipeline::from_options`] for the stages, and [`Pipeline`] to
+/// build your own.
 pub fn convert(
     img: &RgbaImage,
     opt: Options,
     palette: &impl ColorMap<Color = Rgba<u8>>,
 ) -> RgbaImage {
-    // resize the image to simulate averaging of pixels
-    let (w, h) = img.dimensions(); // save width and height for later
-    let mut img = if opt.resize > 1 {
-        // sample using linear filtering
-        resize(img, w - w / opt.resize, h - h / opt.resize, Triangle)
-    } else {
-        img.clone()
-    };
-
-    // dither image using neu-quant quantization
-    if (1..=30).contains(&opt.quantize) {
-        let ref q = NeuQuant::new(opt.quantize, 256, img.as_raw()); // train neural network
-        dither(&mut img, q);
-    }
-
-    // re-color the image using the provided palette
-    dither(&mut img, palette);
-
-    // blur image
-    let img = if opt.blur > 0. {
-        blur(&img, opt.blur)
-    } else {
-        img
-    };
+    Pipeline::from_options(opt, palette, img.dimensions()).run(img)
+}
 
-    // if we resized earlier, restore original size
-    if opt.resize > 1 {
-        resize(&img, w, h, Triangle)
-    } else {
-        img
-    }
+/// Like [`convert`], but reporting progress and checking for cancellation
+/// with `hooks`. See [`progress::Hooks`].
+pub fn convert_with(
+    img: &RgbaImage,
+    opt: Options,
+    palette: &impl ColorMap<Color = Rgba<u8>>,
+    hooks: &Hooks,
+) -> Result<RgbaImage, Cancelled> {
+    Pipeline::from_options(opt, palette, img.dimensions()).run_with(img, hooks)
 }
@@ -0,0 +1,408 @@
+use image::{imageops::ColorMap, Rgb, RgbImage, Rgba, RgbaImage};
+use std::{fmt, str::FromStr};
+
+/// How colors between the points of a [`Lut`] are interpolated.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Interpolation {
+    /// Blend the 8 surrounding points.
+    Trilinear,
+    /// Blend the 4 points of the tetrahedron around the color. This is
+    /// faster and keeps neutral colors neutral, so most tools use it.
+    #[default]
+    Tetrahedral,
+}
+
+impl FromStr for Interpolation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "trilinear" => Ok(Interpolation::Trilinear),
+            "tetrahedral" => Ok(Interpolation::Tetrahedral),
+            _ => Err(format!(
+                "unknown interpolation '{}', expected one of: trilinear, tetrahedral",
+                s
+            )),
+        }
+    }
+}
+
+/// A 3D color lookup table (LUT), mapping input colors to output colors on a
+/// regular grid.
+///
+/// Bake a color map into a LUT with [`Lut::from_color_map`], then export it as
+/// an Adobe/Resolve `.cube` file with [`Lut::to_cube`] or as a HALD CLUT image
+/// with [`Lut::to_hald`]. These can be used to apply the same conversion in
+/// other tools like ffmpeg, Darktable, and GIMP.
+///
+/// LUTs made in other tools can be loaded with [`Lut::from_cube`] and
+/// [`Lut::from_hald`], and applied to images with [`Lut::apply`] or as part of
+/// [`convert`](crate::convert) with [`Options::lut`](crate::Options::lut).
+///
+/// ```
+/// # use image_go_nord::{lut::Lut, palette::PreserveLuminance, NORD};
+/// let lut = Lut::from_color_map(&PreserveLuminance(&NORD), 17);
+/// let cube = lut.to_cube(Some("Nord"));
+/// assert!(cube.contains("LUT_3D_SIZE 17"));
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct Lut {
+    size: usize,
+    /// The input colors mapped by the first and last points along each axis
+    domain: [[f32; 3]; 2],
+    /// Output colors between 0 and 1, with red changing fastest and blue
+    /// changing slowest
+    table: Vec<[f32; 3]>,
+}
+
+impl Lut {
+    /// Bake the mapping performed by [`ColorMap::map_color`] into a LUT with
+    /// `size` points along each axis. Larger LUTs are more accurate but take
+    /// longer to compute; 33 is a common size.
+    ///
+    /// # Panics
+    ///
+    /// The size must be at least 2.
+    pub fn from_color_map(map: &impl ColorMap<Color = Rgba<u8>>, size: usize) -> Self {
+        assert!(
+            size >= 2,
+            "LUTs must have at least 2 points along each axis"
+        );
+        let level = |i: usize| ((i * 255) as f32 / (size - 1) as f32).round() as u8;
+        let table = (0..size * size * size)
+            .map(|i| {
+                let mut color = Rgba([
+                    level(i % size),
+                    level(i / size % size),
+                    level(i / size / size),
+                    0xFF,
+                ]);
+                map.map_color(&mut color);
+                let [r, g, b, _] = color.0;
+                [r as f32 / 255., g as f32 / 255., b as f32 / 255.]
+            })
+            .collect();
+        Lut {
+            size,
+            domain: [[0.; 3], [1.; 3]],
+            table,
+        }
+    }
+
+    /// Parse a LUT in the Adobe/Resolve `.cube` format. Only 3D LUTs are
+    /// supported.
+    ///
+    /// ```
+    /// # use image_go_nord::lut::{Interpolation, Lut};
+    /// let invert = Lut::from_cube("
+    ///     # swaps black and white
+    ///     LUT_3D_SIZE 2
+    ///     1 1 1
+    ///     0 1 1
+    ///     1 0 1
+    ///     0 0 1
+    ///     1 1 0
+    ///     0 1 0
+    ///     1 0 0
+    ///     0 0 0
+    /// ").unwrap();
+    /// let [r, g, b] = invert.sample([0.25, 0.5, 1.], Interpolation::Trilinear);
+    /// assert!((r - 0.75).abs() < 1e-6 && (g - 0.5).abs() < 1e-6 && b.abs() < 1e-6);
+    /// ```
+    pub fn from_cube(s: &str) -> Result<Self, String> {
+        let mut size = None;
+        let mut domain = [[0.; 3], [1.; 3]];
+        let mut table = Vec::new();
+        let triple = |values: &[&str]| -> Result<[f32; 3], String> {
+            match values {
+                [r, g, b] => {
+                    let parse = |v: &str| {
+                        v.parse::<f32>()
+                            .map_err(|_| format!("invalid number '{}' in cube LUT", v))
+                    };
+                    Ok([parse(r)?, parse(g)?, parse(b)?])
+                }
+                _ => Err(format!("expected 3 values, found '{}'", values.join(" "))),
+            }
+        };
+
+        for line in s.lines().map(str::trim) {
+            let words: Vec<_> = line.split_whitespace().collect();
+            match words[..] {
+                [] => {}
+                [first, ..] if first.starts_with('#') || first == "TITLE" => {}
+                ["LUT_3D_SIZE", n] => {
+                    let n = n
+                        .parse::<usize>()
+                        .map_err(|_| format!("invalid LUT size '{}'", n))?;
+                    if n < 2 {
+                        return Err("LUTs must have at least 2 points along each axis".into());
+                    }
+                    size = Some(n)
+                }
+                ["LUT_1D_SIZE", ..] => return Err("1D LUTs are not supported".into()),
+                ["DOMAIN_MIN", ref values @ ..] => domain[0] = triple(values)?,
+                ["DOMAIN_MAX", ref values @ ..] => domain[1] = triple(values)?,
+                // skip other keywords, like LUT_3D_INPUT_RANGE
+                [first, ..] if first.starts_with(char::is_alphabetic) => {}
+                ref values => table.push(triple(values)?),
+            }
+        }
+
+        let size = size.ok_or("missing LUT_3D_SIZE in cube LUT")?;
+        if table.len() != size * size * size {
+            return Err(format!(
+                "expected {} entries in cube LUT of size {}, found {}",
+                size * size * size,
+                size,
+                table.len()
+            ));
+        }
+        Ok(Lut {
+            size,
+            domain,
+            table,
+        })
+    }
+
+    /// Read a LUT from a HALD CLUT image. A HALD image of level `L` is `L³`
+    /// pixels wide and tall, and holds a LUT of size `L²`.
+    pub fn from_hald(img: &RgbImage) -> Result<Self, String> {
+        let (w, h) = img.dimensions();
+        let level = (w as f64).cbrt().round() as u32;
+        if w != h || level * level * level != w || level < 2 {
+            return Err(format!("{}x{} is not the size of a HALD image", w, h));
+        }
+        let table = img
+            .pixels()
+            .map(|&Rgb([r, g, b])| [r as f32 / 255., g as f32 / 255., b as f32 / 255.])
+            .collect();
+        Ok(Lut {
+            size: (level * level) as usize,
+            domain: [[0.; 3], [1.; 3]],
+            table,
+        })
+    }
+
+    /// The number of points along each axis
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Look up a color with channels between 0 and 1 (or the domain of the
+    /// LUT), interpolating between the points around it.
+    pub fn sample(&self, rgb: [f32; 3], interpolation: Interpolation) -> [f32; 3] {
+        let n = self.size;
+        let [min, max] = self.domain;
+        let mut index = [0; 3];
+        let mut frac = [0.; 3];
+        for c in 0..3 {
+            let x = ((rgb[c] - min[c]) / (max[c] - min[c])).clamp(0., 1.) * (n - 1) as f32;
+            index[c] = (x as usize).min(n - 2);
+            frac[c] = x - index[c] as f32;
+        }
+        // the point at an offset of 0 or 1 from `index` along each axis
+        let at = |r: usize, g: usize, b: usize| {
+            self.table[(index[0] + r) + (index[1] + g) * n + (index[2] + b) * n * n]
+        };
+        // the weighted sum of points
+        let blend = |points: &[(f32, [f32; 3])]| {
+            let mut out = [0.; 3];
+            for (weight, point) in points {
+                for c in 0..3 {
+                    out[c] += weight * point[c];
+                }
+            }
+            out
+        };
+
+        let [fr, fg, fb] = frac;
+        match interpolation {
+            Interpolation::Trilinear => {
+                let mut points = Vec::with_capacity(8);
+                for (b, wb) in [(0, 1. - fb), (1, fb)] {
+                    for (g, wg) in [(0, 1. - fg), (1, fg)] {
+                        for (r, wr) in [(0, 1. - fr), (1, fr)] {
+                            points.push((wr * wg * wb, at(r, g, b)));
+                        }
+                    }
+                }
+                blend(&points)
+            }
+            Interpolation::Tetrahedral => {
+                let (c000, c111) = (at(0, 0, 0), at(1, 1, 1));
+                if fr > fg {
+                    if fg > fb {
+                        blend(&[
+                            (1. - fr, c000),
+                            (fr - fg, at(1, 0, 0)),
+                            (fg - fb, at(1, 1, 0)),
+                            (fb, c111),
+                        ])
+                    } else if fr > fb {
+                        blend(&[
+                            (1. - fr, c000),
+                            (fr - fb, at(1, 0, 0)),
+                            (fb - fg, at(1, 0, 1)),
+                            (fg, c111),
+                        ])
+                    } else {
+                        blend(&[
+                            (1. - fb, c000),
+                            (fb - fr, at(0, 0, 1)),
+                            (fr - fg, at(1, 0, 1)),
+                            (fg, c111),
+                        ])
+                    }
+                } else if fb > fg {
+                    blend(&[
+                        (1. - fb, c000),
+                        (fb - fg, at(0, 0, 1)),
+                        (fg - fr, at(0, 1, 1)),
+                        (fr, c111),
+                    ])
+                } else if fb > fr {
+                    blend(&[
+                        (1. - fg, c000),
+                        (fg - fb, at(0, 1, 0)),
+                        (fb - fr, at(0, 1, 1)),
+                        (fr, c111),
+                    ])
+                } else {
+                    blend(&[
+                        (1. - fg, c000),
+                        (fg - fr, at(0, 1, 0)),
+                        (fr - fb, at(1, 1, 0)),
+                        (fb, c111),
+                    ])
+                }
+            }
+        }
+    }
+
+    /// Apply the LUT to every pixel of an image. The alpha channel is kept.
+    pub fn apply(&self, img: &mut RgbaImage, interpolation: Interpolation) {
+        for pixel in img.pixels_mut() {
+            let [r, g, b, _] = pixel.0;
+            let rgb = [r as f32 / 255., g as f32 / 255., b as f32 / 255.];
+            let [r, g, b] = self.sample(rgb, interpolation);
+            let channel = |c: f32| (c * 255.).round().clamp(0., 255.) as u8;
+            pixel.0[..3].copy_from_slice(&[channel(r), channel(g), channel(b)]);
+        }
+    }
+
+    /// Write the LUT in the Adobe/Resolve `.cube` format, with an optional
+    /// title.
+    pub fn to_cube(&self, title: Option<&str>) -> String {
+        let mut out = String::new();
+        if let Some(title) = title {
+            out.push_str(&format!("TITLE \"{}\"\n", title.replace('"', "'")));
+        }
+        out.push_str(&self.to_string());
+        out
+    }
+
+    /// Render the LUT as a HALD CLUT image. A HALD image of level `L` holds a
+    /// LUT of size `L²`, and is `L³` pixels wide and tall.
+    ///
+    /// ```
+    /// # use image_go_nord::{lut::Lut, NORD};
+    /// let hald = Lut::from_color_map(&NORD, 16).to_hald();
+    /// assert_eq!(hald.dimensions(), (64, 64));
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// The size of the LUT must be a square number.
+    pub fn to_hald(&self) -> RgbImage {
+        let level = (self.size as f64).sqrt().round() as u32;
+        assert_eq!(
+            (level * level) as usize,
+            self.size,
+            "HALD images need a LUT size that is a square number"
+        );
+        let side = level * level * level;
+        RgbImage::from_fn(side, side, |x, y| {
+            let [r, g, b] = self.table[(y * side + x) as usize];
+            let channel = |c: f32| (c * 255.).round().clamp(0., 255.) as u8;
+            Rgb([channel(r), channel(g), channel(b)])
+        })
+    }
+}
+
+/// Formats the LUT as the body of a `.cube` file, without a title.
+impl fmt::Display for Lut {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "LUT_3D_SIZE {}", self.size)?;
+        for (keyword, [r, g, b]) in ["DOMAIN_MIN", "DOMAIN_MAX"].iter().zip(self.domain) {
+            writeln!(f, "{} {:.6} {:.6} {:.6}", keyword, r, g, b)?;
+        }
+        for [r, g, b] in &self.table {
+            writeln!(f, "{:.6} {:.6} {:.6}", r, g, b)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::NORD;
+
+    /// Mapping each color to itself
+    struct Identity;
+
+    impl ColorMap for Identity {
+        type Color = Rgba<u8>;
+
+        fn index_of(&self, _: &Self::Color) -> usize {
+            0
+        }
+
+        fn map_color(&self, _: &mut Self::Color) {}
+    }
+
+    #[test]
+    fn test_identity_hald() {
+        let hald = Lut::from_color_map(&Identity, 4).to_hald();
+        assert_eq!(hald.dimensions(), (8, 8));
+        // red changes fastest, then green, then blue
+        assert_eq!(hald.get_pixel(1, 0), &Rgb([85, 0, 0]));
+        assert_eq!(hald.get_pixel(4, 0), &Rgb([0, 85, 0]));
+        assert_eq!(hald.get_pixel(0, 2), &Rgb([0, 0, 85]));
+        assert_eq!(hald.get_pixel(7, 7), &Rgb([255, 255, 255]));
+    }
+
+    #[test]
+    fn test_identity_sample() {
+        let lut = Lut::from_color_map(&Identity, 5);
+        for interpolation in [Interpolation::Trilinear, Interpolation::Tetrahedral] {
+            for rgb in [[0.1, 0.5, 0.9], [0.7, 0.2, 0.4], [1., 0., 0.33]] {
+                let out = lut.sample(rgb, interpolation);
+                for c in 0..3 {
+                    assert!((out[c] - rgb[c]).abs() < 0.01, "{:?} {:?}", rgb, out);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let lut = Lut::from_color_map(&NORD, 4);
+        assert_eq!(Lut::from_hald(&lut.to_hald()).unwrap(), lut);
+        let parsed = Lut::from_cube(&lut.to_cube(Some("Nord"))).unwrap();
+        assert_eq!(parsed.size(), 4);
+        for (a, b) in parsed.table.iter().zip(&lut.table) {
+            assert!((0..3).all(|c| (a[c] - b[c]).abs() < 1e-5));
+        }
+    }
+
+    #[test]
+    fn test_cube_entries() {
+        let cube = Lut::from_color_map(&NORD, 2).to_cube(None);
+        let entries = cube.lines().filter(|l| l.starts_with(char::is_numeric));
+        assert_eq!(entries.count(), 8);
+    }
+}
@@ -0,0 +1,289 @@
+use crate::utils::rgb_to_lab;
+use image::RgbaImage;
+use std::fmt;
+
+/// Standard deviation and radius of the Gaussian window used by [`ssim`]
+const SSIM_SIGMA: f64 = 1.5;
+const SSIM_RADIUS: isize = 5;
+/// Stabilizing constants used by [`ssim`], for 8-bit images
+const SSIM_C1: f64 = (0.01 * 255.) * (0.01 * 255.);
+const SSIM_C2: f64 = (0.03 * 255.) * (0.03 * 255.);
+
+/// Measurements of how faithfully a converted image represents its original.
+///
+/// Use [`measure`] to create a report. `Report` implements
+/// [`std::fmt::Display`], so it can be printed as a one-line summary.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Report {
+    /// Per-pixel color difference statistics
+    pub delta_e: DeltaE,
+    /// Peak signal-to-noise ratio in decibels. Higher is better, and identical
+    /// images have an infinite PSNR.
+    pub psnr: f64,
+    /// Mean structural similarity index, between -1 and 1. Higher is better,
+    /// and identical images have an SSIM of 1.
+    pub ssim: f64,
+}
+
+/// Statistics of the CIEDE2000 color difference (ΔE) between corresponding
+/// pixels. A ΔE of about 1 is the smallest difference most people can notice.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct DeltaE {
+    pub mean: f64,
+    pub median: f64,
+    pub p90: f64,
+    pub p95: f64,
+    pub p99: f64,
+    pub max: f64,
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let DeltaE {
+            mean,
+            median,
+            p95,
+            max,
+            ..
+        } = self.delta_e;
+        write!(
+            f,
+            "ΔE mean {:.2}, median {:.2}, p95 {:.2}, max {:.2}; PSNR {:.2} dB; SSIM {:.4}",
+            mean, median, p95, max, self.psnr, self.ssim
+        )
+    }
+}
+
+/// Compare an original image with its conversion.
+///
+/// Only the color channels are compared; alpha is ignored.
+///
+/// ```
+/// # use image::{Rgba, RgbaImage};
+/// # use image_go_nord::{convert, metrics::measure, NORD};
+/// let original = RgbaImage::from_pixel(16, 16, Rgba([255, 0, 0, 255]));
+///
+/// let identical = measure(&original, &original);
+/// assert_eq!(identical.delta_e.max, 0.);
+/// assert_eq!(identical.psnr, f64::INFINITY);
+///
+/// let converted = convert(&original, Default::default(), &NORD);
+/// let report = measure(&original, &converted);
+/// assert!(report.delta_e.mean > 0.);
+/// assert!(report.ssim < 1.);
+/// ```
+///
+/// # Panics
+///
+/// Both images must have the same dimensions.
+pub fn measure(original: &RgbaImage, converted: &RgbaImage) -> Report {
+    assert_eq!(
+        original.dimensions(),
+        converted.dimensions(),
+        "images must have the same dimensions"
+    );
+    Report {
+        delta_e: delta_e(original, converted),
+        psnr: psnr(original, converted),
+        ssim: ssim(original, converted),
+    }
+}
+
+/// Calculate statistics of the per-pixel CIEDE2000 color difference between
+/// two images of the same size.
+pub fn delta_e(a: &RgbaImage, b: &RgbaImage) -> DeltaE {
+    let mut deltas: Vec<f64> = a
+        .pixels()
+        .zip(b.pixels())
+        .map(|(a, b)| ciede2000(rgb_to_lab(&a.0), rgb_to_lab(&b.0)))
+        .collect();
+    if deltas.is_empty() {
+        return DeltaE::default();
+    }
+    deltas.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+    let percentile = |p: f64| deltas[((deltas.len() - 1) as f64 * p).round() as usize];
+    DeltaE {
+        mean: deltas.iter().sum::<f64>() / deltas.len() as f64,
+        median: percentile(0.5),
+        p90: percentile(0.9),
+        p95: percentile(0.95),
+        p99: percentile(0.99),
+        max: deltas[deltas.len() - 1],
+    }
+}
+
+/// The CIEDE2000 color difference between two colors in CIE L\*a\*b\*.
+///
+/// ```
+/// # use image_go_nord::metrics::ciede2000;
+/// let d = ciede2000([50., 2.6772, -79.7751], [50., 0., -82.7485]);
+/// assert!((d - 2.0425).abs() < 1e-4);
+/// ```
+pub fn ciede2000([l1, a1, b1]: [f32; 3], [l2, a2, b2]: [f32; 3]) -> f64 {
+    let (l1, a1, b1) = (l1 as f64, a1 as f64, b1 as f64);
+    let (l2, a2, b2) = (l2 as f64, a2 as f64, b2 as f64);
+
+    let c_bar = (a1.hypot(b1) + a2.hypot(b2)) / 2.;
+    let g = 0.5 * (1. - (c_bar.powi(7) / (c_bar.powi(7) + 25f64.powi(7))).sqrt());
+    let (a1, a2) = (a1 * (1. + g), a2 * (1. + g));
+    let (c1, c2) = (a1.hypot(b1), a2.hypot(b2));
+    let hue = |a: f64, b: f64| {
+        if a == 0. && b == 0. {
+            0.
+        } else {
+            b.atan2(a).to_degrees().rem_euclid(360.)
+        }
+    };
+    let (h1, h2) = (hue(a1, b1), hue(a2, b2));
+
+    let dl = l2 - l1;
+    let dc = c2 - c1;
+    let dh = if c1 * c2 == 0. {
+        0.
+    } else if (h2 - h1).abs() <= 180. {
+        h2 - h1
+    } else if h2 - h1 > 180. {
+        h2 - h1 - 360.
+    } else {
+        h2 - h1 + 360.
+    };
+    let dh = 2. * (c1 * c2).sqrt() * (dh / 2.).to_radians().sin();
+
+    let l_bar = (l1 + l2) / 2.;
+    let c_bar = (c1 + c2) / 2.;
+    let h_bar = if c1 * c2 == 0. {
+        h1 + h2
+    } else if (h1 - h2).abs() <= 180. {
+        (h1 + h2) / 2.
+    } else if h1 + h2 < 360. {
+        (h1 + h2 + 360.) / 2.
+    } else {
+        (h1 + h2 - 360.) / 2.
+    };
+
+    let t = 1. - 0.17 * (h_bar - 30.).to_radians().cos()
+        + 0.24 * (2. * h_bar).to_radians().cos()
+        + 0.32 * (3. * h_bar + 6.).to_radians().cos()
+        - 0.20 * (4. * h_bar - 63.).to_radians().cos();
+    let d_theta = 30. * (-((h_bar - 275.) / 25.).powi(2)).exp();
+    let r_c = 2. * (c_bar.powi(7) / (c_bar.powi(7) + 25f64.powi(7))).sqrt();
+    let s_l = 1. + 0.015 * (l_bar - 50.).powi(2) / (20. + (l_bar - 50.).powi(2)).sqrt();
+    let s_c = 1. + 0.045 * c_bar;
+    let s_h = 1. + 0.015 * c_bar * t;
+    let r_t = -(2. * d_theta).to_radians().sin() * r_c;
+
+    let (dl, dc, dh) = (dl / s_l, dc / s_c, dh / s_h);
+    (dl * dl + dc * dc + dh * dh + r_t * dc * dh).sqrt()
+}
+
+/// Peak signal-to-noise ratio between the color channels of two images of the
+/// same size, in decibels.
+pub fn psnr(a: &RgbaImage, b: &RgbaImage) -> f64 {
+    let mut sum = 0.;
+    for (a, b) in a.pixels().zip(b.pixels()) {
+        for c in 0..3 {
+            sum += (a[c] as f64 - b[c] as f64).powi(2);
+        }
+    }
+    if sum == 0. {
+        return f64::INFINITY;
+    }
+    let mse = sum / (a.pixels().len() * 3) as f64;
+    10. * (255f64.powi(2) / mse).log10()
+}
+
+/// Mean structural similarity index between the luma of two images of the
+/// same size, using an 11x11 Gaussian window.
+pub fn ssim(a: &RgbaImage, b: &RgbaImage) -> f64 {
+    let (w, h) = a.dimensions();
+    if w == 0 || h == 0 {
+        return 1.;
+    }
+    let luma = |img: &RgbaImage| -> Vec<f64> {
+        img.pixels()
+            .map(|p| 0.299 * p[0] as f64 + 0.587 * p[1] as f64 + 0.114 * p[2] as f64)
+            .collect()
+    };
+    let (x, y) = (luma(a), luma(b));
+    let product =
+        |a: &[f64], b: &[f64]| -> Vec<f64> { a.iter().zip(b).map(|(a, b)| a * b).collect() };
+
+    let mu_x = gaussian(&x, w, h);
+    let mu_y = gaussian(&y, w, h);
+    let xx = gaussian(&product(&x, &x), w, h);
+    let yy = gaussian(&product(&y, &y), w, h);
+    let xy = gaussian(&product(&x, &y), w, h);
+
+    let total: f64 = (0..x.len())
+        .map(|i| {
+            let (mx, my) = (mu_x[i], mu_y[i]);
+            let var_x = xx[i] - mx * mx;
+            let var_y = yy[i] - my * my;
+            let cov = xy[i] - mx * my;
+            ((2. * mx * my + SSIM_C1) * (2. * cov + SSIM_C2))
+                / ((mx * mx + my * my + SSIM_C1) * (var_x + var_y + SSIM_C2))
+        })
+        .sum();
+    total / x.len() as f64
+}
+
+/// Separable Gaussian blur of a single-channel buffer, clamping at the edges
+fn gaussian(data: &[f64], w: u32, h: u32) -> Vec<f64> {
+    let (w, h) = (w as usize, h as usize);
+    let kernel: Vec<f64> = (-SSIM_RADIUS..=SSIM_RADIUS)
+        .map(|i| (-(i * i) as f64 / (2. * SSIM_SIGMA * SSIM_SIGMA)).exp())
+        .collect();
+    let norm: f64 = kernel.iter().sum();
+
+    let blur = |data: &[f64], stride: usize, len: usize, lines: usize, step: usize| {
+        let mut out = vec![0.; data.len()];
+        for line in 0..lines {
+            for i in 0..len {
+                let sum: f64 = kernel
+                    .iter()
+                    .enumerate()
+                    .map(|(k, weight)| {
+                        let j = (i as isize + k as isize - SSIM_RADIUS).clamp(0, len as isize - 1);
+                        weight * data[line * step + j as usize * stride]
+                    })
+                    .sum();
+                out[line * step + i * stride] = sum / norm;
+            }
+        }
+        out
+    };
+    let horizontal = blur(data, 1, w, h, w);
+    blur(&horizontal, w, h, w, 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ciede2000;
+
+    /// Test data from Sharma, Wu, and Dalal (2005)
+    #[test]
+    fn test_ciede2000_reference() {
+        let pairs = [
+            ([50., 2.6772, -79.7751], [50., 0., -82.7485], 2.0425),
+            ([50., -1.3802, -84.2814], [50., 0., -82.7485], 1.),
+            ([50., 2.5, 0.], [73., 25., -18.], 27.1492),
+            ([50., 2.5, 0.], [50., 3.1736, 0.5854], 1.),
+            (
+                [60.2574, -34.0099, 36.2677],
+                [60.4626, -34.1751, 39.4387],
+                1.2644,
+            ),
+            (
+                [22.7233, 20.0904, -46.694],
+                [23.0331, 14.973, -42.5619],
+                2.0373,
+            ),
+        ];
+        for (a, b, expected) in pairs {
+            let d = ciede2000(a, b);
+            assert!((d - expected).abs() < 1e-4, "{:?} {:?}: {}", a, b, d);
+            assert!((ciede2000(b, a) - expected).abs() < 1e-4);
+        }
+    }
+}
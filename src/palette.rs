@@ -1,4 +1,7 @@
-use crate::utils::delta;
+use crate::{
+    metrics::ciede2000,
+    utils::{delta, hsl_to_rgb, lab_to_rgb, rgb_to_hsl, rgb_to_lab},
+};
 use image::{imageops::ColorMap, Pixel, Rgb, Rgba};
 use itertools::Itertools;
 use smallvec::SmallVec;
@@ -155,7 +158,7 @@ impl ColorMap for DynamicPalette {
         // calculate the delta between the given color and a color in the palette
         self.colors
             .iter()
-            .map(|c: &Entry| dbg!(delta(color.channels(), c.as_ref())))
+            .map(|c: &Entry| delta(color.channels(), c.as_ref()))
             .position_min()
             .unwrap()
     }
@@ -177,6 +180,156 @@ impl ColorMap for DynamicPalette {
     }
 }
 
+/// Wraps a color map so that matched colors keep the lightness of the original
+/// color. Only the hue and chroma (the a\* and b\* components in CIE
+/// L\*a\*b\*) are taken from the closest palette entry.
+///
+/// This keeps the lightness structure of photos intact, at the cost of
+/// producing colors that are not strictly part of the palette.
+///
+/// ```
+/// # use image::{imageops::ColorMap, Rgba};
+/// # use image_go_nord::{palette::PreserveLuminance, utils::luminance, NORD};
+/// let mut color = Rgba([90, 110, 140, 255]);
+/// PreserveLuminance(&NORD).map_color(&mut color);
+/// assert!((luminance(&color.0) - luminance(&[90, 110, 140])).abs() < 0.01);
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct PreserveLuminance<'a, P: ?Sized>(pub &'a P);
+
+impl<P> ColorMap for PreserveLuminance<'_, P>
+where
+    P: ColorMap<Color = Rgba<u8>> + ?Sized,
+{
+    type Color = Rgba<u8>;
+
+    fn index_of(&self, color: &Self::Color) -> usize {
+        self.0.index_of(color)
+    }
+
+    fn map_color(&self, color: &mut Self::Color) {
+        let [l, ..] = rgb_to_lab(color.channels());
+        let mut matched = *color;
+        self.0.map_color(&mut matched);
+        let [_, a, b] = rgb_to_lab(matched.channels());
+        // don't overwrite the alpha channel
+        color.channels_mut()[..3].copy_from_slice(&lab_to_rgb([l, a, b]))
+    }
+}
+
+/// Wraps a color map so that colors are matched and mapped by hue alone. The
+/// palette entry with the closest hue is chosen, and only its hue is applied;
+/// the saturation and lightness of the original color are kept.
+///
+/// Achromatic palette entries (like pure grays) have no meaningful hue, so
+/// they are only chosen when the palette has no other entries. The wrapped
+/// color map must support [`ColorMap::lookup`] for entries to be compared by
+/// hue, otherwise its own matching is used.
+///
+/// ```
+/// # use image::{imageops::ColorMap, Rgba};
+/// # use image_go_nord::{palette::HueOnly, utils::rgb_to_hsl, AURORA};
+/// let mut color = Rgba([255, 20, 20, 255]);
+/// HueOnly(&AURORA).map_color(&mut color);
+/// // the red in Aurora is slightly pink
+/// assert!(rgb_to_hsl(&color.0)[0] > 350.);
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct HueOnly<'a, P: ?Sized>(pub &'a P);
+
+impl<P> ColorMap for HueOnly<'_, P>
+where
+    P: ColorMap<Color = Rgba<u8>> + ?Sized,
+{
+    type Color = Rgba<u8>;
+
+    fn index_of(&self, color: &Self::Color) -> usize {
+        if !self.0.has_lookup() {
+            return self.0.index_of(color);
+        }
+        let [h, ..] = rgb_to_hsl(color.channels());
+        (0..)
+            .map_while(|i| self.0.lookup(i))
+            .map(|entry| {
+                let [e_h, s, _] = rgb_to_hsl(entry.channels());
+                if s == 0. {
+                    // achromatic entries are as far away as possible
+                    180.
+                } else {
+                    let d = (h - e_h).abs();
+                    d.min(360. - d)
+                }
+            })
+            .position_min_by(|a, b| a.partial_cmp(b).unwrap())
+            .unwrap()
+    }
+
+    fn map_color(&self, color: &mut Self::Color) {
+        let matched = self.0.lookup(self.index_of(color)).unwrap_or_else(|| {
+            let mut matched = *color;
+            self.0.map_color(&mut matched);
+            matched
+        });
+        let [h, ..] = rgb_to_hsl(matched.channels());
+        let [_, s, l] = rgb_to_hsl(color.channels());
+        // don't overwrite the alpha channel
+        color.channels_mut()[..3].copy_from_slice(&hsl_to_rgb([h, s, l]))
+    }
+}
+
+/// Wraps a color map so that colors are matched by perceptual distance
+/// (CIEDE2000) instead of Euclidean distance in RGB. This tends to pick
+/// entries that look closer, especially for dark and saturated colors, but is
+/// considerably slower.
+///
+/// The wrapped color map must support [`ColorMap::lookup`] for entries to be
+/// compared, otherwise its own matching is used.
+///
+/// ```
+/// # use image::{imageops::ColorMap, Rgba};
+/// # use image_go_nord::{palette::Perceptual, NORD};
+/// let mut color = Rgba([0x88, 0xC0, 0xD0, 255]);
+/// Perceptual(&NORD).map_color(&mut color);
+/// assert_eq!(color, Rgba([0x88, 0xC0, 0xD0, 255]));
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct Perceptual<'a, P: ?Sized>(pub &'a P);
+
+impl<P> ColorMap for Perceptual<'_, P>
+where
+    P: ColorMap<Color = Rgba<u8>> + ?Sized,
+{
+    type Color = Rgba<u8>;
+
+    fn index_of(&self, color: &Self::Color) -> usize {
+        if !self.0.has_lookup() {
+            return self.0.index_of(color);
+        }
+        let lab = rgb_to_lab(color.channels());
+        (0..)
+            .map_while(|i| self.0.lookup(i))
+            .map(|entry| ciede2000(lab, rgb_to_lab(entry.channels())))
+            .position_min_by(|a, b| a.partial_cmp(b).unwrap())
+            .unwrap()
+    }
+
+    fn map_color(&self, color: &mut Self::Color) {
+        match self.0.lookup(self.index_of(color)) {
+            // don't overwrite the alpha channel
+            Some(matched) => color.channels_mut()[..3].copy_from_slice(&matched.channels()[..3]),
+            None => self.0.map_color(color),
+        }
+    }
+
+    fn has_lookup(&self) -> bool {
+        self.0.has_lookup()
+    }
+
+    fn lookup(&self, index: usize) -> Option<Self::Color> {
+        self.0.lookup(index)
+    }
+}
+
 pub mod palettes {
     use super::StaticPalette;
     pub static AURORA: StaticPalette<5> =
@@ -209,4 +362,12 @@ pub mod palettes {
         SNOW_STORM.colors[1],
         SNOW_STORM.colors[2],
     ]);
+
+    /// The 16 ANSI terminal colors of the Nord terminal themes, in slot order:
+    /// black, red, green, yellow, blue, magenta, cyan, and white, followed by
+    /// their bright variants.
+    pub static TERMINAL: StaticPalette<16> = StaticPalette::from_rgb_hex([
+        0x3B4252, 0xBF616A, 0xA3BE8C, 0xEBCB8B, 0x81A1C1, 0xB48EAD, 0x88C0D0, 0xE5E9F0, 0x4C566A,
+        0xBF616A, 0xA3BE8C, 0xEBCB8B, 0x81A1C1, 0xB48EAD, 0x8FBCBB, 0xECEFF4,
+    ]);
 }
@@ -0,0 +1,429 @@
+use crate::{
+    assignment::Assignment,
+    lut::{Interpolation, Lut},
+    palette::{HueOnly, PreserveLuminance},
+    progress::{Cancelled, Hooks, Tracker},
+    Mode, Options,
+};
+use color_quant::NeuQuant;
+use image::{
+    imageops::{blur, resize, unsharpen, ColorMap, FilterType::Triangle},
+    Rgba, RgbaImage,
+};
+
+/// A single processing step of a [`Pipeline`].
+///
+/// Stages take the image produced by the previous stage and return the next
+/// one. They also get the original image, so they can restore its size or
+/// blend with it. Implement this trait to add your own stages.
+///
+/// ```
+/// # use image::RgbaImage;
+/// # use image_go_nord::pipeline::{Pipeline, Stage};
+/// /// Flips the image upside down
+/// struct Flip;
+///
+/// impl Stage for Flip {
+///     fn apply(&self, img: RgbaImage, _original: &RgbaImage) -> RgbaImage {
+///         image::imageops::flip_vertical(&img)
+///     }
+/// }
+///
+/// let img = RgbaImage::from_fn(1, 2, |_, y| image::Rgba([y as u8, 0, 0, 255]));
+/// let flipped = Pipeline::new().stage(Flip).run(&img);
+/// assert_eq!(flipped.get_pixel(0, 0)[0], 1);
+/// ```
+pub trait Stage {
+    fn apply(&self, img: RgbaImage, original: &RgbaImage) -> RgbaImage;
+
+    /// Like [`apply`](Stage::apply), but reporting progress to `tracker` and
+    /// stopping early when the conversion is cancelled. Stages that take a
+    /// while should override this; by default, the stage only reports when
+    /// it starts and ends, through [`Pipeline::run_with`].
+    fn apply_with(
+        &self,
+        img: RgbaImage,
+        original: &RgbaImage,
+        _tracker: &Tracker,
+    ) -> Result<RgbaImage, Cancelled> {
+        Ok(self.apply(img, original))
+    }
+}
+
+/// Any function or closure taking the current and the original image can be
+/// used as a stage.
+impl<F> Stage for F
+where
+    F: Fn(RgbaImage, &RgbaImage) -> RgbaImage,
+{
+    fn apply(&self, img: RgbaImage, original: &RgbaImage) -> RgbaImage {
+        self(img, original)
+    }
+}
+
+/// An ordered list of [`Stage`]s that an image is passed through.
+///
+/// Stages can be ordered, repeated, or left out freely. [`convert`] is the
+/// pipeline built by [`Pipeline::from_options`].
+///
+/// ```
+/// # use image::{Rgba, RgbaImage};
+/// # use image_go_nord::{pipeline::*, Mode, NORD};
+/// let img = RgbaImage::from_pixel(32, 32, Rgba([200, 100, 50, 255]));
+/// let pipeline = Pipeline::new()
+///     .stage(Resize { width: 16, height: 16 })
+///     .stage(PaletteMap { palette: &NORD, mode: Mode::Luminance, dither: true })
+///     .stage(Sharpen { sigma: 1., threshold: 2 })
+///     .stage(RestoreSize)
+///     .stage(Mix(0.8));
+/// assert_eq!(pipeline.run(&img).dimensions(), (32, 32));
+/// ```
+///
+/// [`convert`]: crate::convert
+#[derive(Default)]
+pub struct Pipeline<'a> {
+    stages: Vec<Box<dyn Stage + 'a>>,
+}
+
+impl<'a> Pipeline<'a> {
+    /// An empty pipeline, which returns images unchanged
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a stage to the end of the pipeline.
+    pub fn stage(mut self, stage: impl Stage + 'a) -> Self {
+        self.push(stage);
+        self
+    }
+
+    /// Add a stage to the end of the pipeline.
+    pub fn push(&mut self, stage: impl Stage + 'a) {
+        self.stages.push(Box::new(stage))
+    }
+
+    /// The number of stages in the pipeline
+    pub fn len(&self) -> usize {
+        self.stages.len()
+    }
+
+    /// Whether the pipeline has no stages
+    pub fn is_empty(&self) -> bool {
+        self.stages.is_empty()
+    }
+
+    /// Pass an image through every stage, in order.
+    pub fn run(&self, img: &RgbaImage) -> RgbaImage {
+        self.stages
+            .iter()
+            .fold(img.clone(), |current, stage| stage.apply(current, img))
+    }
+
+    /// Pass an image through every stage, in order, reporting progress and
+    /// checking for cancellation with `hooks`.
+    ///
+    /// ```
+    /// # use image::{Rgba, RgbaImage};
+    /// # use image_go_nord::{pipeline::*, progress::*, Mode, NORD};
+    /// # use std::sync::Mutex;
+    /// let img = RgbaImage::from_pixel(4, 4, Rgba([200, 100, 50, 255]));
+    /// let pipeline = Pipeline::new()
+    ///     .stage(PaletteMap { palette: &NORD, mode: Mode::Nearest, dither: true })
+    ///     .stage(Blur(1.));
+    /// let reports = Mutex::new(Vec::new());
+    /// let hooks = Hooks {
+    ///     progress: Some(&|p: Progress| reports.lock().unwrap().push(p.total())),
+    ///     ..Default::default()
+    /// };
+    /// assert_eq!(pipeline.run_with(&img, &hooks), Ok(pipeline.run(&img)));
+    /// // the start and end of each stage, and every row of the palette mapping
+    /// assert_eq!(reports.lock().unwrap().len(), 2 + 4 + 2);
+    /// assert_eq!(reports.lock().unwrap().last(), Some(&1.));
+    /// ```
+    pub fn run_with(&self, img: &RgbaImage, hooks: &Hooks) -> Result<RgbaImage, Cancelled> {
+        let stages = self.len();
+        self.stages
+            .iter()
+            .enumerate()
+            .try_fold(img.clone(), |current, (i, stage)| {
+                let tracker = Tracker::new(hooks, i, stages);
+                tracker.report(0.)?;
+                let next = stage.apply_with(current, img, &tracker)?;
+                tracker.report(1.)?;
+                Ok(next)
+            })
+    }
+
+    /// The pipeline used by [`convert`](crate::convert): resize, quantize,
+    /// apply the LUT, map to the palette, blur, and restore the original size,
+    /// leaving out the stages that `opt` disables. `dimensions` are the
+    /// dimensions of the images that will be converted, which are needed to
+    /// resize them.
+    #[allow(deprecated)]
+    pub fn from_options<P>(opt: Options, palette: &'a P, (w, h): (u32, u32)) -> Self
+    where
+        P: ColorMap<Color = Rgba<u8>>,
+    {
+        let mut pipeline = Pipeline::new();
+        // resize the image to simulate averaging of pixels
+        if opt.resize > 1 {
+            pipeline.push(Resize {
+                width: w - w / opt.resize,
+                height: h - h / opt.resize,
+            });
+        }
+        if (1..=30).contains(&opt.quantize) {
+            pipeline.push(Quantize(opt.quantize));
+        }
+        // apply the LUT before palette matching, so looks can be combined with it
+        if let Some(lut) = opt.lut {
+            pipeline.push(ApplyLut(lut, opt.lut_interpolation));
+        }
+        if !opt.skip_palette {
+            pipeline.push(PaletteMap {
+                palette,
+                mode: opt.mode,
+                dither: opt.dither,
+            });
+        }
+        if opt.blur > 0. {
+            pipeline.push(Blur(opt.blur));
+        }
+        if opt.resize > 1 {
+            pipeline.push(RestoreSize);
+        }
+        pipeline
+    }
+}
+
+/// Resize the image to the given dimensions using linear filtering.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Resize {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Stage for Resize {
+    fn apply(&self, img: RgbaImage, _: &RgbaImage) -> RgbaImage {
+        resize(&img, self.width, self.height, Triangle)
+    }
+}
+
+/// Resize the image back to the dimensions of the original, if they differ.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RestoreSize;
+
+impl Stage for RestoreSize {
+    fn apply(&self, img: RgbaImage, original: &RgbaImage) -> RgbaImage {
+        let (w, h) = original.dimensions();
+        if img.dimensions() == (w, h) {
+            img
+        } else {
+            resize(&img, w, h, Triangle)
+        }
+    }
+}
+
+/// Quantize the image to 256 colors with NeuQuant and dither it, using the
+/// given sample factor between 1 and 30. See [`Options::quantize`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Quantize(pub i32);
+
+impl Stage for Quantize {
+    fn apply(&self, img: RgbaImage, original: &RgbaImage) -> RgbaImage {
+        untracked(self, img, original)
+    }
+
+    fn apply_with(
+        &self,
+        mut img: RgbaImage,
+        _: &RgbaImage,
+        tracker: &Tracker,
+    ) -> Result<RgbaImage, Cancelled> {
+        let q = &NeuQuant::new(self.0, 256, img.as_raw()); // train neural network
+        recolor(&mut img, q, true, tracker)?;
+        Ok(img)
+    }
+}
+
+/// Dither the image with any color map, like a palette or one of the palette
+/// wrappers.
+#[derive(Clone, Copy, Debug)]
+pub struct Dither<M>(pub M);
+
+impl<M> Stage for Dither<M>
+where
+    M: ColorMap<Color = Rgba<u8>>,
+{
+    fn apply(&self, img: RgbaImage, original: &RgbaImage) -> RgbaImage {
+        untracked(self, img, original)
+    }
+
+    fn apply_with(
+        &self,
+        mut img: RgbaImage,
+        _: &RgbaImage,
+        tracker: &Tracker,
+    ) -> Result<RgbaImage, Cancelled> {
+        recolor(&mut img, &self.0, true, tracker)?;
+        Ok(img)
+    }
+}
+
+/// Recolor the image with a palette, in one of the [`Mode`]s used by
+/// [`convert`](crate::convert), with or without dithering. See
+/// [`Options::dither`].
+#[derive(Clone, Copy, Debug)]
+pub struct PaletteMap<'a, P> {
+    pub palette: &'a P,
+    pub mode: Mode,
+    pub dither: bool,
+}
+
+impl<P> Stage for PaletteMap<'_, P>
+where
+    P: ColorMap<Color = Rgba<u8>>,
+{
+    fn apply(&self, img: RgbaImage, original: &RgbaImage) -> RgbaImage {
+        untracked(self, img, original)
+    }
+
+    fn apply_with(
+        &self,
+        mut img: RgbaImage,
+        _: &RgbaImage,
+        tracker: &Tracker,
+    ) -> Result<RgbaImage, Cancelled> {
+        let palette = self.palette;
+        match self.mode {
+            Mode::Nearest => recolor(&mut img, palette, self.dither, tracker)?,
+            Mode::Luminance => {
+                recolor(&mut img, &PreserveLuminance(palette), self.dither, tracker)?
+            }
+            Mode::Hue => recolor(&mut img, &HueOnly(palette), self.dither, tracker)?,
+            Mode::Balanced => {
+                let entries = (0..).take_while(|&i| palette.lookup(i).is_some()).count();
+                // the entries of the palette are needed to assign clusters to
+                // them, so palettes without any fall back to nearest matching
+                if palette.has_lookup() && entries > 0 {
+                    let assignment = Assignment::new(&img, palette, entries);
+                    recolor(&mut img, &assignment, self.dither, tracker)?
+                } else {
+                    recolor(&mut img, palette, self.dither, tracker)?
+                }
+            }
+        }
+        Ok(img)
+    }
+}
+
+/// Apply a 3D LUT with the given interpolation. See [`Lut::apply`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct ApplyLut(pub Lut, pub Interpolation);
+
+impl Stage for ApplyLut {
+    fn apply(&self, mut img: RgbaImage, _: &RgbaImage) -> RgbaImage {
+        self.0.apply(&mut img, self.1);
+        img
+    }
+}
+
+/// Perform a Gaussian blur with the given 𝛔.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Blur(pub f32);
+
+impl Stage for Blur {
+    fn apply(&self, img: RgbaImage, _: &RgbaImage) -> RgbaImage {
+        blur(&img, self.0)
+    }
+}
+
+/// Sharpen the image with an unsharp mask. `sigma` is the amount to blur the
+/// mask by, and `threshold` is the smallest difference that is sharpened.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sharpen {
+    pub sigma: f32,
+    pub threshold: i32,
+}
+
+impl Stage for Sharpen {
+    fn apply(&self, img: RgbaImage, _: &RgbaImage) -> RgbaImage {
+        unsharpen(&img, self.sigma, self.threshold)
+    }
+}
+
+/// Blend the image with the original, keeping the given fraction of the
+/// processed image: `Mix(1.0)` keeps only the processed image and `Mix(0.0)`
+/// only the original. The original is resized if the dimensions differ.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Mix(pub f32);
+
+impl Stage for Mix {
+    fn apply(&self, mut img: RgbaImage, original: &RgbaImage) -> RgbaImage {
+        let (w, h) = img.dimensions();
+        let resized;
+        let original = if original.dimensions() != (w, h) {
+            resized = resize(original, w, h, Triangle);
+            &resized
+        } else {
+            original
+        };
+        let t = self.0.clamp(0., 1.);
+        for (p, o) in img.pixels_mut().zip(original.pixels()) {
+            for c in 0..4 {
+                p[c] = (o[c] as f32 + (p[c] as f32 - o[c] as f32) * t).round() as u8;
+            }
+        }
+        img
+    }
+}
+
+/// Apply a stage that overrides [`Stage::apply_with`] without any hooks.
+fn untracked(stage: &impl Stage, img: RgbaImage, original: &RgbaImage) -> RgbaImage {
+    let hooks = Hooks::default();
+    stage
+        .apply_with(img, original, &Tracker::new(&hooks, 0, 1))
+        .expect("conversions without a cancellation token can't be cancelled")
+}
+
+/// Map every pixel with a color map, with Floyd–Steinberg dithering like
+/// [`image::imageops::dither`] if `dither` is set. Rows are mapped one at a
+/// time, so progress is reported and cancellation checked after each row.
+fn recolor<M>(
+    img: &mut RgbaImage,
+    map: &M,
+    dither: bool,
+    tracker: &Tracker,
+) -> Result<(), Cancelled>
+where
+    M: ColorMap<Color = Rgba<u8>> + ?Sized,
+{
+    let (width, height) = img.dimensions();
+    for y in 0..height {
+        for x in 0..width {
+            let old = *img.get_pixel(x, y);
+            let new = img.get_pixel_mut(x, y);
+            map.map_color(new);
+            if !dither {
+                continue;
+            }
+            let mut err = [0; 3];
+            for c in 0..3 {
+                err[c] = old[c] as i16 - new[c] as i16;
+            }
+            // spread the error to the pixels right of and below this one
+            for &(dx, dy, factor) in &[(1, 0, 7), (-1, 1, 3), (0, 1, 5), (1, 1, 1)] {
+                let (nx, ny) = (x as i64 + dx, y as i64 + dy);
+                if nx < 0 || nx >= width as i64 || ny >= height as i64 {
+                    continue;
+                }
+                let pixel = img.get_pixel_mut(nx as u32, ny as u32);
+                for c in 0..3 {
+                    pixel[c] = (pixel[c] as i16 + err[c] * factor / 16).clamp(0, 0xFF) as u8;
+                }
+            }
+        }
+        tracker.report((y + 1) as f32 / height as f32)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,101 @@
+use crate::{Mode, Options};
+#[cfg(all(feature = "serde", any(feature = "toml", feature = "serde_json")))]
+use std::collections::BTreeMap;
+
+/// Names of the built-in presets, in the order they are listed in the docs of
+/// [`built_in`]
+pub const BUILT_IN: [&str; 4] = ["icon", "photo", "wallpaper", "pixel-art"];
+
+/// Look up one of the built-in presets for common use cases:
+///
+/// - `icon` spreads the few colors of icons and logos across the whole
+///   palette with [`Mode::Balanced`].
+/// - `photo` keeps the lightness structure of photos with
+///   [`Mode::Luminance`] and quantizes them first to reduce noise.
+/// - `wallpaper` also keeps lightness, and blurs slightly so large gradients
+///   stay smooth.
+/// - `pixel-art` snaps every pixel to the closest palette color without
+///   dithering, keeping flat areas and hard edges intact.
+///
+/// ```
+/// # use image_go_nord::{presets, Mode};
+/// let photo = presets::built_in("photo").unwrap();
+/// assert_eq!(photo.mode, Mode::Luminance);
+/// assert!(presets::built_in("portrait").is_none());
+/// ```
+pub fn built_in(name: &str) -> Option<Options> {
+    let options = match name {
+        "icon" => Options {
+            mode: Mode::Balanced,
+            ..Default::default()
+        },
+        "photo" => Options {
+            mode: Mode::Luminance,
+            quantize: 10,
+            ..Default::default()
+        },
+        "wallpaper" => Options {
+            mode: Mode::Luminance,
+            blur: 0.5,
+            ..Default::default()
+        },
+        "pixel-art" => Options {
+            mode: Mode::Nearest,
+            dither: false,
+            ..Default::default()
+        },
+        _ => return None,
+    };
+    Some(options)
+}
+
+/// Parse user-defined presets from TOML, where each table is a preset named by
+/// its key. Fields that are left out take their default values.
+///
+/// ```
+/// # use image_go_nord::{presets, Mode};
+/// let presets = presets::from_toml(r#"
+///     [soft]
+///     mode = "luminance"
+///     blur = 0.3
+/// "#).unwrap();
+/// assert_eq!(presets["soft"].mode, Mode::Luminance);
+/// assert_eq!(presets["soft"].quantize, 0);
+/// ```
+#[cfg(all(feature = "serde", feature = "toml"))]
+pub fn from_toml(s: &str) -> Result<BTreeMap<String, Options>, String> {
+    toml::from_str(s).map_err(|e| e.to_string())
+}
+
+/// Parse user-defined presets from a JSON object, where each field is a preset
+/// named by its key. Fields that are left out take their default values.
+#[cfg(all(feature = "serde", feature = "serde_json"))]
+pub fn from_json(s: &str) -> Result<BTreeMap<String, Options>, String> {
+    serde_json::from_str(s).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{convert, NORD};
+    use image::{imageops::ColorMap, Rgba, RgbaImage};
+
+    #[test]
+    fn test_built_in_differ_from_default() {
+        for name in BUILT_IN {
+            assert_ne!(built_in(name).unwrap(), Options::default(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_pixel_art_does_not_dither() {
+        let img = RgbaImage::from_fn(16, 1, |x, _| Rgba([x as u8 * 16, 100, 150, 255]));
+        let converted = convert(&img, built_in("pixel-art").unwrap(), &NORD);
+        for (original, converted) in img.pixels().zip(converted.pixels()) {
+            let mut nearest = *original;
+            NORD.map_color(&mut nearest);
+            assert_eq!(converted, &nearest);
+        }
+        assert_ne!(converted, convert(&img, Options::default(), &NORD));
+    }
+}
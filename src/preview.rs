@@ -0,0 +1,269 @@
+use color_quant::NeuQuant;
+use image::{
+    codecs::png::PngEncoder,
+    imageops::{resize, FilterType::Triangle},
+    ColorType, Rgba, RgbaImage,
+};
+use std::{collections::HashMap, fmt::Write, str::FromStr};
+
+/// The assumed width of a terminal cell in pixels, used to scale sixel and
+/// kitty images to a number of columns
+const CELL_WIDTH: u32 = 8;
+/// The largest payload of a single kitty graphics escape sequence
+const KITTY_CHUNK: usize = 4096;
+/// Pixels with an alpha below this are drawn as transparent
+const ALPHA_THRESHOLD: u8 = 128;
+
+/// How an image is drawn in a terminal.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Protocol {
+    /// Upper half block characters with 24-bit ANSI colors, drawing two pixels
+    /// per cell. This works in most modern terminals.
+    #[default]
+    Blocks,
+    /// DEC sixel graphics, supported by xterm, mlterm, foot, WezTerm, and
+    /// others. Sixel images are limited to 256 colors.
+    Sixel,
+    /// The kitty graphics protocol, supported by kitty, WezTerm, and Konsole.
+    Kitty,
+}
+
+impl FromStr for Protocol {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "blocks" | "half-blocks" => Ok(Protocol::Blocks),
+            "sixel" => Ok(Protocol::Sixel),
+            "kitty" => Ok(Protocol::Kitty),
+            _ => Err(format!(
+                "unknown protocol '{}', expected one of: blocks, sixel, kitty",
+                s
+            )),
+        }
+    }
+}
+
+/// Render an image as a string of escape sequences that draws it in a
+/// terminal, at most `columns` cells wide.
+///
+/// Images are scaled down to fit, but never scaled up. Sixel and kitty images
+/// are sized assuming each cell is 8 pixels wide. The output ends with a
+/// newline, so the cursor is left on the line below the image.
+///
+/// ```
+/// # use image::{Rgba, RgbaImage};
+/// # use image_go_nord::preview::{preview, Protocol};
+/// let img = RgbaImage::from_pixel(4, 4, Rgba([0x88, 0xC0, 0xD0, 0xFF]));
+///
+/// let blocks = preview(&img, Protocol::Blocks, 80);
+/// assert_eq!(blocks.lines().count(), 2);
+/// assert!(blocks.contains("\x1b[38;2;136;192;208m"));
+///
+/// let sixel = preview(&img, Protocol::Sixel, 80);
+/// assert!(sixel.starts_with("\x1bP"));
+/// ```
+pub fn preview(img: &RgbaImage, protocol: Protocol, columns: u32) -> String {
+    match protocol {
+        Protocol::Blocks => blocks(&fit(img, columns.max(1))),
+        Protocol::Sixel => sixel(&fit(img, columns.max(1) * CELL_WIDTH)),
+        Protocol::Kitty => kitty(img, columns.max(1)),
+    }
+}
+
+/// Scale an image down to at most `width` pixels wide, keeping its aspect ratio
+fn fit(img: &RgbaImage, width: u32) -> RgbaImage {
+    let (w, h) = img.dimensions();
+    if w <= width {
+        return img.clone();
+    }
+    let height = ((h as u64 * width as u64) / w as u64).max(1) as u32;
+    resize(img, width, height, Triangle)
+}
+
+fn blocks(img: &RgbaImage) -> String {
+    let (w, h) = img.dimensions();
+    let mut out = String::new();
+    for y in (0..h).step_by(2) {
+        for x in 0..w {
+            let top = Some(img.get_pixel(x, y)).filter(|p| p[3] >= ALPHA_THRESHOLD);
+            let bottom = Some(y + 1)
+                .filter(|&y| y < h)
+                .map(|y| img.get_pixel(x, y))
+                .filter(|p| p[3] >= ALPHA_THRESHOLD);
+            // writing to a string can't fail
+            let _ = match (top, bottom) {
+                (Some(Rgba([r, g, b, _])), Some(Rgba([br, bg, bb, _]))) => write!(
+                    out,
+                    "\x1b[38;2;{};{};{}m\x1b[48;2;{};{};{}m▀",
+                    r, g, b, br, bg, bb
+                ),
+                (Some(Rgba([r, g, b, _])), None) => {
+                    write!(out, "\x1b[49m\x1b[38;2;{};{};{}m▀", r, g, b)
+                }
+                (None, Some(Rgba([r, g, b, _]))) => {
+                    write!(out, "\x1b[49m\x1b[38;2;{};{};{}m▄", r, g, b)
+                }
+                (None, None) => write!(out, "\x1b[0m "),
+            };
+        }
+        out.push_str("\x1b[0m\n");
+    }
+    out
+}
+
+fn sixel(img: &RgbaImage) -> String {
+    let (w, h) = img.dimensions();
+    let (colors, indices) = index_colors(img);
+
+    // P2 = 1 leaves pixels that aren't drawn transparent
+    let mut out = format!("\x1bP0;1;0q\"1;1;{};{}", w, h);
+    for (i, Rgba([r, g, b, _])) in colors.iter().enumerate() {
+        let percent = |c: u8| (c as u32 * 100 + 127) / 255;
+        let _ = write!(
+            out,
+            "#{};2;{};{};{}",
+            i,
+            percent(*r),
+            percent(*g),
+            percent(*b)
+        );
+    }
+
+    // each band is 6 pixels tall and drawn once for every color it contains
+    for band in (0..h).step_by(6) {
+        let rows = (band..(band + 6).min(h)).collect::<Vec<_>>();
+        let mut first = true;
+        for color in 0..colors.len() {
+            let sixels: Vec<u8> = (0..w)
+                .map(|x| {
+                    rows.iter().enumerate().fold(0, |bits, (bit, &y)| {
+                        if indices[(y * w + x) as usize] == Some(color) {
+                            bits | 1 << bit
+                        } else {
+                            bits
+                        }
+                    })
+                })
+                .collect();
+            if sixels.iter().all(|&bits| bits == 0) {
+                continue;
+            }
+            if !first {
+                out.push('$');
+            }
+            first = false;
+            let _ = write!(out, "#{}", color);
+            write_runs(&mut out, &sixels);
+        }
+        out.push('-');
+    }
+    out.push_str("\x1b\\\n");
+    out
+}
+
+/// Write sixels with run-length encoding
+fn write_runs(out: &mut String, sixels: &[u8]) {
+    let mut i = 0;
+    while i < sixels.len() {
+        let bits = sixels[i];
+        let run = sixels[i..].iter().take_while(|&&b| b == bits).count();
+        let c = (b'?' + bits) as char;
+        if run > 3 {
+            let _ = write!(out, "!{}{}", run, c);
+        } else {
+            (0..run).for_each(|_| out.push(c));
+        }
+        i += run;
+    }
+}
+
+/// Build a palette of at most 256 colors for an image, along with the palette
+/// index of each pixel, or `None` for transparent pixels. Images with more
+/// colors are quantized.
+fn index_colors(img: &RgbaImage) -> (Vec<Rgba<u8>>, Vec<Option<usize>>) {
+    let opaque = |p: &&Rgba<u8>| p[3] >= ALPHA_THRESHOLD;
+    let mut lookup = HashMap::new();
+    let mut colors = Vec::new();
+    for p in img.pixels().filter(opaque) {
+        let color = Rgba([p[0], p[1], p[2], 0xFF]);
+        lookup.entry(color).or_insert_with(|| {
+            colors.push(color);
+            colors.len() - 1
+        });
+    }
+
+    if colors.len() <= 256 {
+        let indices = img
+            .pixels()
+            .map(|p| {
+                Some(p)
+                    .filter(opaque)
+                    .map(|p| lookup[&Rgba([p[0], p[1], p[2], 0xFF])])
+            })
+            .collect();
+        return (colors, indices);
+    }
+
+    let q = NeuQuant::new(10, 256, img.as_raw());
+    let colors = q
+        .color_map_rgba()
+        .chunks_exact(4)
+        .map(|c| Rgba([c[0], c[1], c[2], 0xFF]))
+        .collect();
+    let indices = img
+        .pixels()
+        .map(|p| Some(p).filter(opaque).map(|p| q.index_of(&p.0)))
+        .collect();
+    (colors, indices)
+}
+
+fn kitty(img: &RgbaImage, columns: u32) -> String {
+    let (w, h) = img.dimensions();
+    let mut png = Vec::new();
+    PngEncoder::new(&mut png)
+        .encode(img.as_raw(), w, h, ColorType::Rgba8)
+        .expect("encoding a PNG in memory can't fail");
+    let data = base64::encode(&png);
+
+    // only scale the image if it is wider than the terminal
+    let scale = if w > columns * CELL_WIDTH {
+        format!(",c={}", columns)
+    } else {
+        String::new()
+    };
+    let mut out = String::new();
+    let chunks: Vec<_> = data.as_bytes().chunks(KITTY_CHUNK).collect();
+    for (i, chunk) in chunks.iter().enumerate() {
+        let more = (i + 1 < chunks.len()) as u8;
+        let chunk = std::str::from_utf8(chunk).unwrap();
+        let _ = if i == 0 {
+            write!(out, "\x1b_Ga=T,f=100{},m={};{}\x1b\\", scale, more, chunk)
+        } else {
+            write!(out, "\x1b_Gm={};{}\x1b\\", more, chunk)
+        };
+    }
+    out.push('\n');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fit() {
+        let img = RgbaImage::new(200, 100);
+        assert_eq!(fit(&img, 50).dimensions(), (50, 25));
+        assert_eq!(fit(&img, 400).dimensions(), (200, 100));
+    }
+
+    #[test]
+    fn test_write_runs() {
+        let mut out = String::new();
+        write_runs(&mut out, &[0, 0, 63, 63, 63, 63, 1]);
+        assert_eq!(out, "??!4~@");
+    }
+}
@@ -0,0 +1,149 @@
+use std::{
+    error::Error,
+    fmt,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+/// A flag that stops a conversion when it is set, usually from another thread.
+///
+/// Clones share the same flag, so keep one to cancel the conversion and pass
+/// the other in [`Hooks`].
+///
+/// ```
+/// # use image_go_nord::progress::CancellationToken;
+/// let token = CancellationToken::new();
+/// let handle = token.clone();
+/// handle.cancel();
+/// assert!(token.is_cancelled());
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stop every conversion using this token, or a clone of it.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed)
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// The error returned when a conversion was cancelled with a
+/// [`CancellationToken`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("the conversion was cancelled")
+    }
+}
+
+impl Error for Cancelled {}
+
+/// How far a conversion is.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Progress {
+    /// The index of the current stage of the pipeline
+    pub stage: usize,
+    /// The number of stages in the pipeline
+    pub stages: usize,
+    /// How much of the current stage is done, from 0 to 1
+    pub fraction: f32,
+}
+
+impl Progress {
+    /// How much of the whole conversion is done, from 0 to 1, assuming every
+    /// stage takes as long.
+    pub fn total(&self) -> f32 {
+        if self.stages == 0 {
+            1.
+        } else {
+            (self.stage as f32 + self.fraction) / self.stages as f32
+        }
+    }
+}
+
+/// Hooks to follow and stop a conversion, used by
+/// [`Pipeline::run_with`](crate::pipeline::Pipeline::run_with) and
+/// [`convert_with`](crate::convert_with).
+///
+/// Progress is reported at the start and end of every stage, and after each
+/// row of the stages that dither. The cancellation token is checked at the
+/// same points.
+///
+/// ```
+/// # use image::{Rgba, RgbaImage};
+/// # use image_go_nord::{convert_with, progress::*, NORD};
+/// let img = RgbaImage::from_pixel(8, 8, Rgba([200, 100, 50, 255]));
+/// let token = CancellationToken::new();
+/// let hooks = Hooks {
+///     progress: Some(&|progress: Progress| println!("{:.0}%", progress.total() * 100.)),
+///     cancel: Some(token.clone()),
+/// };
+/// assert!(convert_with(&img, Default::default(), &NORD, &hooks).is_ok());
+///
+/// token.cancel();
+/// assert_eq!(convert_with(&img, Default::default(), &NORD, &hooks), Err(Cancelled));
+/// ```
+#[derive(Clone, Default)]
+pub struct Hooks<'a> {
+    /// Called as the conversion progresses
+    pub progress: Option<&'a (dyn Fn(Progress) + Sync)>,
+    /// Stops the conversion when it is cancelled
+    pub cancel: Option<CancellationToken>,
+}
+
+impl fmt::Debug for Hooks<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Hooks")
+            .field("progress", &self.progress.map(|_| ".."))
+            .field("cancel", &self.cancel)
+            .finish()
+    }
+}
+
+/// Passed to [`Stage::apply_with`](crate::pipeline::Stage::apply_with), so
+/// stages can report their progress and stop early when they are cancelled.
+#[derive(Clone, Copy, Debug)]
+pub struct Tracker<'a> {
+    hooks: &'a Hooks<'a>,
+    stage: usize,
+    stages: usize,
+}
+
+impl<'a> Tracker<'a> {
+    /// A tracker for the given stage of a pipeline with `stages` stages
+    pub fn new(hooks: &'a Hooks<'a>, stage: usize, stages: usize) -> Self {
+        Tracker {
+            hooks,
+            stage,
+            stages,
+        }
+    }
+
+    /// Report that `fraction` of the stage is done, and check whether the
+    /// conversion was cancelled.
+    pub fn report(&self, fraction: f32) -> Result<(), Cancelled> {
+        if self.hooks.cancel.as_ref().is_some_and(|c| c.is_cancelled()) {
+            return Err(Cancelled);
+        }
+        if let Some(progress) = self.hooks.progress {
+            progress(Progress {
+                stage: self.stage,
+                stages: self.stages,
+                fraction,
+            })
+        }
+        Ok(())
+    }
+}
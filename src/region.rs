@@ -0,0 +1,215 @@
+use image::{
+    imageops::{blur, resize, FilterType::Triangle},
+    DynamicImage, GenericImageView, GrayImage, Luma, Pixel, RgbaImage,
+};
+use std::str::FromStr;
+
+/// A rectangular region of an image, in pixels.
+///
+/// Regions can be parsed from strings of the form `x,y,width,height`.
+///
+/// ```
+/// # use image_go_nord::region::Region;
+/// let region: Region = "10,20,300,400".parse().unwrap();
+/// assert_eq!(region, Region { x: 10, y: 20, width: 300, height: 400 });
+/// ```
+#[cfg_attr(feature = "serde", derive(serde::Deserialize))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Region {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Region {
+    /// Shrink the region so that it fits inside an image with the given
+    /// dimensions.
+    pub fn clamp(self, (width, height): (u32, u32)) -> Self {
+        let x = self.x.min(width);
+        let y = self.y.min(height);
+        Region {
+            x,
+            y,
+            width: self.width.min(width - x),
+            height: self.height.min(height - y),
+        }
+    }
+}
+
+impl FromStr for Region {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts = s
+            .split(',')
+            .map(|p| p.trim().parse::<u32>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("invalid region '{}': {}", s, e))?;
+        match parts[..] {
+            [x, y, width, height] => Ok(Region {
+                x,
+                y,
+                width,
+                height,
+            }),
+            _ => Err(format!(
+                "invalid region '{}': expected four numbers, 'x,y,width,height'",
+                s
+            )),
+        }
+    }
+}
+
+/// Convert only a rectangular region of an image, leaving the rest untouched.
+///
+/// `convert` is called on a copy of the region, so any conversion (like
+/// [`convert`](crate::convert) or [`gradient_map`](crate::gradient::gradient_map))
+/// can be used. If `feather` is positive, the converted region fades into the
+/// original image over that many pixels along the edges of the region that are
+/// inside the image.
+///
+/// # Panics
+///
+/// `convert` must return an image with the same dimensions as the region it
+/// is given.
+///
+/// ```
+/// # use image::{Rgba, RgbaImage};
+/// # use image_go_nord::{convert, region::{convert_region, Region}, NORD};
+/// let img = RgbaImage::from_pixel(4, 4, Rgba([255, 0, 0, 255]));
+/// // convert the right half
+/// let region = Region { x: 2, y: 0, width: 2, height: 4 };
+/// let result = convert_region(&img, region, 0., |img| convert(img, Default::default(), &NORD));
+///
+/// assert_eq!(result.get_pixel(0, 0), &Rgba([255, 0, 0, 255]));
+/// assert_ne!(result.get_pixel(3, 0), &Rgba([255, 0, 0, 255]));
+/// ```
+pub fn convert_region(
+    img: &RgbaImage,
+    region: Region,
+    feather: f32,
+    convert: impl FnOnce(&RgbaImage) -> RgbaImage,
+) -> RgbaImage {
+    let Region {
+        x,
+        y,
+        width,
+        height,
+    } = region.clamp(img.dimensions());
+    let mut result = img.clone();
+    if width == 0 || height == 0 {
+        return result;
+    }
+    let converted = convert(&img.view(x, y, width, height).to_image());
+    assert_eq!(
+        converted.dimensions(),
+        (width, height),
+        "the converted region must have the same dimensions as the region"
+    );
+
+    // only feather the edges that don't touch the border of the image
+    let (img_w, img_h) = img.dimensions();
+    let edges = [x > 0, y > 0, x + width < img_w, y + height < img_h];
+    for (cx, cy, pixel) in converted.enumerate_pixels() {
+        let weight = if feather > 0. {
+            let distances = [cx, cy, width - 1 - cx, height - 1 - cy];
+            let d = edges
+                .iter()
+                .zip(&distances)
+                .filter(|(&feathered, _)| feathered)
+                .map(|(_, &d)| d)
+                .min();
+            d.map_or(1., |d| ((d as f32 + 0.5) / feather).min(1.))
+        } else {
+            1.
+        };
+        blend(
+            result.get_pixel_mut(x + cx, y + cy),
+            pixel.channels(),
+            weight,
+        );
+    }
+    result
+}
+
+/// Convert only the pixels of an image selected by a mask, leaving the rest
+/// untouched.
+///
+/// Each pixel of the mask is the weight of the converted image in the result,
+/// so white pixels are fully converted, black pixels are left as they are, and
+/// gray pixels are blended. If the mask does not have the same dimensions as
+/// the image, it is stretched to fit. If `feather` is positive, the edges of
+/// the mask are softened to fade over about that many pixels, like the edges of
+/// [`convert_region`].
+///
+/// # Panics
+///
+/// `convert` must return an image with the same dimensions as the image it is
+/// given.
+///
+/// ```
+/// # use image::{GrayImage, Luma, Rgba, RgbaImage};
+/// # use image_go_nord::{convert, region::convert_masked, NORD};
+/// let img = RgbaImage::from_pixel(2, 2, Rgba([255, 0, 0, 255]));
+/// // only convert the top left pixel
+/// let mut mask = GrayImage::new(2, 2);
+/// mask.put_pixel(0, 0, Luma([255]));
+/// let result = convert_masked(&img, &mask, 0., |img| convert(img, Default::default(), &NORD));
+///
+/// assert_ne!(result.get_pixel(0, 0), &Rgba([255, 0, 0, 255]));
+/// assert_eq!(result.get_pixel(1, 1), &Rgba([255, 0, 0, 255]));
+/// ```
+pub fn convert_masked(
+    img: &RgbaImage,
+    mask: &GrayImage,
+    feather: f32,
+    convert: impl FnOnce(&RgbaImage) -> RgbaImage,
+) -> RgbaImage {
+    let (width, height) = img.dimensions();
+    let mut mask = if mask.dimensions() != (width, height) {
+        resize(mask, width, height, Triangle)
+    } else {
+        mask.clone()
+    };
+    if feather > 0. {
+        // a Gaussian blur fades edges over about 3𝛔 on each side
+        mask = blur(&mask, feather / 3.);
+    }
+
+    let mut result = convert(img);
+    assert_eq!(
+        result.dimensions(),
+        (width, height),
+        "the converted image must have the same dimensions as the original"
+    );
+    for ((original, converted), Luma([m])) in
+        img.pixels().zip(result.pixels_mut()).zip(mask.pixels())
+    {
+        let mut pixel = *original;
+        blend(&mut pixel, converted.channels(), *m as f32 / 255.);
+        *converted = pixel;
+    }
+    result
+}
+
+/// Create a mask from an image to use with [`convert_masked`]. Images with an
+/// alpha channel use their alpha channel as the mask, while other images are
+/// converted to grayscale.
+pub fn mask_from_image(img: &DynamicImage) -> GrayImage {
+    if img.color().has_alpha() {
+        let rgba = img.to_rgba8();
+        GrayImage::from_fn(rgba.width(), rgba.height(), |x, y| {
+            Luma([rgba.get_pixel(x, y)[3]])
+        })
+    } else {
+        img.to_luma8()
+    }
+}
+
+/// Blend `over` into `base` with the given weight between 0 and 1
+fn blend<P: Pixel<Subpixel = u8>>(base: &mut P, over: &[u8], weight: f32) {
+    for (b, &o) in base.channels_mut().iter_mut().zip(over) {
+        *b = (*b as f32 + (o as f32 - *b as f32) * weight).round() as u8;
+    }
+}
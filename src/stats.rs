@@ -0,0 +1,84 @@
+use crate::{metrics::ciede2000, utils::rgb_to_lab};
+use image::{imageops::ColorMap, Rgba, RgbaImage};
+
+/// How much a single palette entry is used in a converted image.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EntryUsage {
+    /// Index of the entry in the palette
+    pub index: usize,
+    /// Color of the entry
+    pub color: Rgba<u8>,
+    /// Number of pixels mapped to this entry
+    pub pixels: u64,
+    /// Fraction of the counted pixels mapped to this entry, between 0 and 1
+    pub fraction: f64,
+    /// Average CIEDE2000 color difference between the original pixels and the
+    /// converted pixels mapped to this entry. This is 0 for unused entries.
+    pub mean_error: f64,
+}
+
+/// Count how often each palette entry is used in a converted image.
+///
+/// Each pixel of `converted` is assigned to a palette entry using
+/// [`ColorMap::index_of`], and its mapping error is measured against the
+/// corresponding pixel of `original`. The result has one row for every palette
+/// entry, in palette order, including entries that were never used. Fully
+/// transparent pixels are not counted.
+///
+/// ```
+/// # use image::{Rgba, RgbaImage};
+/// # use image_go_nord::{convert, stats::usage, POLAR_NIGHT};
+/// let original = RgbaImage::from_pixel(4, 4, Rgba([40, 40, 50, 255]));
+/// let converted = convert(&original, Default::default(), &POLAR_NIGHT);
+///
+/// let usage = usage(&original, &converted, &POLAR_NIGHT);
+/// assert_eq!(usage.len(), 4);
+/// assert_eq!(usage.iter().map(|u| u.pixels).sum::<u64>(), 16);
+/// ```
+///
+/// # Panics
+///
+/// Both images must have the same dimensions, and the palette must support
+/// [`ColorMap::lookup`].
+pub fn usage(
+    original: &RgbaImage,
+    converted: &RgbaImage,
+    palette: &impl ColorMap<Color = Rgba<u8>>,
+) -> Vec<EntryUsage> {
+    assert_eq!(
+        original.dimensions(),
+        converted.dimensions(),
+        "images must have the same dimensions"
+    );
+    assert!(palette.has_lookup(), "palettes must support lookup");
+    let colors: Vec<_> = (0..).map_while(|i| palette.lookup(i)).collect();
+
+    let mut pixels = vec![0u64; colors.len()];
+    let mut errors = vec![0f64; colors.len()];
+    for (o, c) in original.pixels().zip(converted.pixels()) {
+        if c[3] == 0 {
+            continue;
+        }
+        let i = palette.index_of(c);
+        pixels[i] += 1;
+        errors[i] += ciede2000(rgb_to_lab(&o.0), rgb_to_lab(&c.0));
+    }
+
+    let total = pixels.iter().sum::<u64>().max(1) as f64;
+    colors
+        .into_iter()
+        .zip(pixels.into_iter().zip(errors))
+        .enumerate()
+        .map(|(index, (color, (pixels, error)))| EntryUsage {
+            index,
+            color,
+            pixels,
+            fraction: pixels as f64 / total,
+            mean_error: if pixels > 0 {
+                error / pixels as f64
+            } else {
+                0.
+            },
+        })
+        .collect()
+}
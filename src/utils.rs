@@ -46,6 +46,142 @@ pub fn delta(a: &[u8], b: &[u8]) -> i32 {
         .sum()
 }
 
+/// Relative luminance of an sRGB color, between 0 and 1.
+///
+/// Uses the Rec. 709 coefficients on linearized components, so the result is
+/// the `Y` component of the color in CIE XYZ.
+///
+/// ```
+/// # use image_go_nord::utils::luminance;
+/// assert_eq!(luminance(&[0, 0, 0]), 0.0);
+/// assert!((luminance(&[255, 255, 255]) - 1.0).abs() < 1e-6);
+/// ```
+pub fn luminance(rgb: &[u8]) -> f32 {
+    let [r, g, b] = linearize(rgb);
+    0.2126 * r + 0.7152 * g + 0.0722 * b
+}
+
+/// Convert an sRGB color to CIE L\*a\*b\* (D65 white point).
+///
+/// Only the first three components of `rgb` are used, so rgba colors can be
+/// passed directly.
+pub fn rgb_to_lab(rgb: &[u8]) -> [f32; 3] {
+    let [r, g, b] = linearize(rgb);
+    let x = (0.4124 * r + 0.3576 * g + 0.1805 * b) / WHITE[0];
+    let y = (0.2126 * r + 0.7152 * g + 0.0722 * b) / WHITE[1];
+    let z = (0.0193 * r + 0.1192 * g + 0.9505 * b) / WHITE[2];
+
+    let f = |t: f32| {
+        if t > EPSILON {
+            t.cbrt()
+        } else {
+            (KAPPA * t + 16.) / 116.
+        }
+    };
+    let (fx, fy, fz) = (f(x), f(y), f(z));
+    [116. * fy - 16., 500. * (fx - fy), 200. * (fy - fz)]
+}
+
+/// Convert a CIE L\*a\*b\* color back to sRGB, clamping colors that fall
+/// outside of the sRGB gamut.
+pub fn lab_to_rgb([l, a, b]: [f32; 3]) -> [u8; 3] {
+    let fy = (l + 16.) / 116.;
+    let fx = fy + a / 500.;
+    let fz = fy - b / 200.;
+
+    let f_inv = |t: f32| {
+        if t.powi(3) > EPSILON {
+            t.powi(3)
+        } else {
+            (116. * t - 16.) / KAPPA
+        }
+    };
+    let (x, y, z) = (
+        f_inv(fx) * WHITE[0],
+        f_inv(fy) * WHITE[1],
+        f_inv(fz) * WHITE[2],
+    );
+
+    delinearize([
+        3.2406 * x - 1.5372 * y - 0.4986 * z,
+        -0.9689 * x + 1.8758 * y + 0.0415 * z,
+        0.0557 * x - 0.2040 * y + 1.0570 * z,
+    ])
+}
+
+/// Convert an sRGB color to hue, saturation, and lightness.
+///
+/// Hue is in degrees in the range `[0, 360)`, while saturation and lightness
+/// are between 0 and 1. Achromatic colors have a hue of 0.
+pub fn rgb_to_hsl(rgb: &[u8]) -> [f32; 3] {
+    let [r, g, b] = [rgb[0], rgb[1], rgb[2]].map(|c| c as f32 / 255.);
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let l = (max + min) / 2.;
+    let d = max - min;
+    if d == 0. {
+        return [0., 0., l];
+    }
+
+    let s = d / (1. - (2. * l - 1.).abs());
+    let h = if max == r {
+        ((g - b) / d).rem_euclid(6.)
+    } else if max == g {
+        (b - r) / d + 2.
+    } else {
+        (r - g) / d + 4.
+    };
+    [h * 60., s, l]
+}
+
+/// Convert hue, saturation, and lightness back to an sRGB color. This is the
+/// inverse of [`rgb_to_hsl`].
+pub fn hsl_to_rgb([h, s, l]: [f32; 3]) -> [u8; 3] {
+    let c = (1. - (2. * l - 1.).abs()) * s;
+    let h = h.rem_euclid(360.) / 60.;
+    let x = c * (1. - (h % 2. - 1.).abs());
+    let [r, g, b] = match h as u32 {
+        0 => [c, x, 0.],
+        1 => [x, c, 0.],
+        2 => [0., c, x],
+        3 => [0., x, c],
+        4 => [x, 0., c],
+        _ => [c, 0., x],
+    };
+    let m = l - c / 2.;
+    [r, g, b].map(|v| ((v + m) * 255.).round().clamp(0., 255.) as u8)
+}
+
+/// Reference white (D65) in CIE XYZ
+const WHITE: [f32; 3] = [0.95047, 1., 1.08883];
+const EPSILON: f32 = 216. / 24389.;
+const KAPPA: f32 = 24389. / 27.;
+
+/// Convert gamma-encoded sRGB components to linear light
+fn linearize(rgb: &[u8]) -> [f32; 3] {
+    [rgb[0], rgb[1], rgb[2]].map(|c| {
+        let c = c as f32 / 255.;
+        if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    })
+}
+
+/// Convert linear light components back to gamma-encoded sRGB
+fn delinearize(rgb: [f32; 3]) -> [u8; 3] {
+    rgb.map(|c| {
+        let c = c.clamp(0., 1.);
+        let c = if c <= 0.0031308 {
+            12.92 * c
+        } else {
+            1.055 * c.powf(1. / 2.4) - 0.055
+        };
+        (c * 255.).round() as u8
+    })
+}
+
 #[cfg(test)]
 pub fn setup_tinycross() -> Result<assert_fs::TempDir, assert_fs::fixture::FixtureError> {
     use std::path::Path;
@@ -63,7 +199,7 @@ pub fn setup_tinycross() -> Result<assert_fs::TempDir, assert_fs::fixture::Fixtu
 
 #[cfg(test)]
 mod tests {
-    use crate::utils::delta;
+    use crate::utils::{delta, hsl_to_rgb, lab_to_rgb, rgb_to_hsl, rgb_to_lab};
 
     /// This covers the most common use case
     #[test]
@@ -83,4 +219,30 @@ mod tests {
         const LEN: usize = 33026;
         assert!(delta(&[u8::MAX; LEN], &[0; LEN]) > i32::MAX)
     }
+
+    #[test]
+    fn test_lab_round_trip() {
+        for rgb in [
+            [0, 0, 0],
+            [255, 255, 255],
+            [46, 52, 64],
+            [191, 97, 106],
+            [136, 192, 208],
+        ] {
+            assert_eq!(lab_to_rgb(rgb_to_lab(&rgb)), rgb)
+        }
+    }
+
+    #[test]
+    fn test_hsl_round_trip() {
+        for rgb in [
+            [0, 0, 0],
+            [255, 255, 255],
+            [46, 52, 64],
+            [191, 97, 106],
+            [136, 192, 208],
+        ] {
+            assert_eq!(hsl_to_rgb(rgb_to_hsl(&rgb)), rgb)
+        }
+    }
 }
@@ -1,11 +1,25 @@
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use assert_cmd::Command;
 use assert_fs::{
-    fixture::{FileWriteFile, FixtureError, PathChild},
+    fixture::{FileWriteFile, FileWriteStr, FixtureError, PathChild},
     TempDir,
 };
 
+/// Home directory of the tests, so they don't read the config file of whoever
+/// runs them
+fn home() -> PathBuf {
+    Path::new(env!("CARGO_TARGET_TMPDIR")).join("home")
+}
+
+/// Run `ign` without the user's config file
+fn ign() -> Command {
+    let mut cmd = Command::cargo_bin("ign").unwrap();
+    cmd.env("HOME", home())
+        .env("XDG_CONFIG_HOME", home().join(".config"));
+    cmd
+}
+
 pub fn setup_tinycross() -> Result<TempDir, FixtureError> {
     let temp = TempDir::new()?;
     let input_file = temp.child("tinycross.png");
@@ -15,14 +29,13 @@ pub fn setup_tinycross() -> Result<TempDir, FixtureError> {
 
 #[test]
 fn test_fails_without_input() {
-    Command::cargo_bin("ign").unwrap().assert().failure();
+    ign().assert().failure();
 }
 
 #[test]
 fn test_same_dir() {
     let temp = setup_tinycross().unwrap();
-    Command::cargo_bin("ign")
-        .unwrap()
+    ign()
         .current_dir(temp.path())
         .arg("tinycross.png")
         .arg("tinycross-nord.png")
@@ -33,8 +46,7 @@ fn test_same_dir() {
 #[test]
 fn test_same_dir_relative() {
     let temp = setup_tinycross().unwrap();
-    Command::cargo_bin("ign")
-        .unwrap()
+    ign()
         .current_dir(temp.path())
         .arg("./tinycross.png")
         .arg("./tinycross-nord.png")
@@ -44,8 +56,7 @@ fn test_same_dir_relative() {
 #[test]
 fn test_same_dir_absolute() {
     let temp = setup_tinycross().unwrap();
-    Command::cargo_bin("ign")
-        .unwrap()
+    ign()
         .current_dir(temp.path())
         .arg(temp.child("tinycross.png").path())
         .arg(temp.child("tinycross-nord.png").path())
@@ -57,10 +68,710 @@ fn test_same_dir_absolute() {
 fn test_dir() {
     let temp = setup_tinycross().unwrap();
     let out = TempDir::new().unwrap();
-    Command::cargo_bin("ign")
+    ign().arg(temp.path()).arg(out.path()).assert().success();
+}
+
+#[test]
+fn test_dir_with_failures() {
+    let temp = setup_tinycross().unwrap();
+    temp.child("notes.txt").write_str("not an image").unwrap();
+    temp.child("broken.png").write_str("not a png").unwrap();
+    let out = TempDir::new().unwrap();
+
+    let assert = ign().arg(temp.path()).arg(out.path()).assert().success();
+    let stderr = String::from_utf8_lossy(&assert.get_output().stderr);
+    assert!(stderr.contains("broken.png"));
+    assert!(stderr.contains("1 converted, 1 skipped, 1 failed"));
+    assert!(out.child("tinycross.png").path().is_file());
+
+    ign()
+        .arg("--fail-fast")
+        .arg(temp.path())
+        .arg(out.path())
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_jobs() {
+    let temp = setup_tinycross().unwrap();
+    for name in ["a.png", "b.png", "c.png", "d.png"] {
+        temp.child(name).write_str("not a png").unwrap();
+    }
+    let out = TempDir::new().unwrap();
+
+    let assert = ign()
+        .args(["--jobs", "2", "--max-memory", "1"])
+        .arg(temp.path())
+        .arg(out.path())
+        .assert()
+        .success();
+    let stderr = String::from_utf8_lossy(&assert.get_output().stderr);
+    assert!(stderr.contains("1 converted, 0 skipped, 4 failed"));
+    // errors are printed in order, even though images are converted in parallel
+    let failed: Vec<_> = stderr
+        .lines()
+        .filter_map(|line| line.split("failed to convert ").nth(1))
+        .map(|rest| &rest[..5])
+        .collect();
+    assert_eq!(failed, ["a.png", "b.png", "c.png", "d.png"]);
+    assert!(out.child("tinycross.png").path().is_file());
+}
+
+#[test]
+fn test_json() {
+    let temp = setup_tinycross().unwrap();
+    temp.child("notes.txt").write_str("not an image").unwrap();
+    temp.child("broken.png").write_str("not a png").unwrap();
+    let out = TempDir::new().unwrap();
+
+    let assert = ign()
+        .args(["--json", "--report"])
+        .arg(temp.path())
+        .arg(out.path())
+        .assert()
+        .success();
+    let records: Vec<serde_json::Value> = String::from_utf8_lossy(&assert.get_output().stdout)
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(records.len(), 3);
+    // images converted in parallel can finish in any order
+    let find = |key: &str, value: &str| {
+        records
+            .iter()
+            .find(|record| record[key].as_str().unwrap_or_default().ends_with(value))
+            .unwrap()
+    };
+    let (broken, tinycross) = (find("input", "broken.png"), find("input", "tinycross.png"));
+    let summary = find("type", "summary");
+    assert_eq!(broken["status"], "failed");
+    assert!(broken["error"].is_string());
+    assert_eq!(tinycross["status"], "converted");
+    assert_eq!(tinycross["palette"], "nord");
+    assert_eq!(tinycross["options"]["mode"], "nearest");
+    assert_eq!(
+        (&tinycross["width"], &tinycross["height"]),
+        (&16.into(), &16.into())
+    );
+    assert!(tinycross["output"]
+        .as_str()
         .unwrap()
+        .ends_with("tinycross.png"));
+    assert!(tinycross["metrics"]["psnr"].is_number());
+    assert_eq!(summary["type"], "summary");
+    assert_eq!(
+        (
+            &summary["converted"],
+            &summary["skipped"],
+            &summary["failed"]
+        ),
+        (&1.into(), &1.into(), &1.into())
+    );
+    // the errors and summary are in the records instead
+    assert!(assert.get_output().stderr.is_empty());
+}
+
+#[test]
+fn test_json_options() {
+    let temp = setup_tinycross().unwrap();
+    let assert = ign()
+        .current_dir(temp.path())
+        .args(["--json", "--preset", "photo", "--no-dither"])
+        .args(["--region", "0,0,8,8", "tinycross.png", "out.png"])
+        .assert()
+        .success();
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    let record: serde_json::Value = serde_json::from_str(stdout.lines().next().unwrap()).unwrap();
+    // the options the image was converted with, not the defaults
+    let options = &record["options"];
+    assert_eq!(options["mode"], "luminance");
+    assert_eq!(options["quantize"], 10);
+    assert_eq!(options["no-dither"], true);
+    assert_eq!(options["region"], serde_json::json!([0, 0, 8, 8]));
+}
+
+#[test]
+fn test_dry_run() {
+    let temp = setup_tinycross().unwrap();
+    temp.child("notes.txt").write_str("not an image").unwrap();
+    let out = TempDir::new().unwrap();
+    out.child("tinycross.png").write_str("old").unwrap();
+
+    let assert = ign()
+        .arg("--dry-run")
+        .arg(temp.path())
+        .arg(out.path())
+        .assert()
+        .success();
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    let stderr = String::from_utf8_lossy(&assert.get_output().stderr);
+    assert!(stdout.contains("tinycross.png (overwrite)"));
+    assert!(stdout.contains("notes.txt (skip)"));
+    assert!(stderr.contains("1 to convert, 1 to overwrite, 1 skipped"));
+    // nothing was converted
+    assert_eq!(
+        std::fs::read_to_string(out.child("tinycross.png").path()).unwrap(),
+        "old"
+    );
+}
+
+#[test]
+fn test_recursive() {
+    let temp = setup_tinycross().unwrap();
+    let tinycross = Path::new(env!("CARGO_MANIFEST_DIR")).join("images/tinycross.png");
+    temp.child("icons/small/cross.png")
+        .write_file(&tinycross)
+        .unwrap();
+    temp.child("icons/small/skip.png")
+        .write_file(&tinycross)
+        .unwrap();
+    let out = TempDir::new().unwrap();
+
+    ign()
+        .args([
+            "--recursive",
+            "--include",
+            "icons/**",
+            "--exclude",
+            "**/skip.png",
+        ])
+        .arg(temp.path())
+        .arg(out.path())
+        .assert()
+        .success();
+    assert!(out.child("icons/small/cross.png").path().is_file());
+    assert!(!out.child("icons/small/skip.png").path().exists());
+    assert!(!out.child("tinycross.png").path().exists());
+}
+
+#[test]
+fn test_recursive_into_input() {
+    let temp = setup_tinycross().unwrap();
+    let out = temp.child("out");
+    std::fs::create_dir(out.path()).unwrap();
+
+    // the second run must not convert the output of the first
+    for _ in 0..2 {
+        let assert = ign()
+            .arg("--recursive")
+            .arg(temp.path())
+            .arg(out.path())
+            .assert()
+            .success();
+        let stderr = String::from_utf8_lossy(&assert.get_output().stderr);
+        assert!(stderr.contains("1 converted, 0 skipped, 0 failed"));
+    }
+    assert!(out.child("tinycross.png").path().is_file());
+    assert!(!out.child("out").path().exists());
+
+    let assert = ign()
+        .args(["--extensions", ".PNG,.jpg"])
+        .arg(temp.path())
+        .arg(out.path())
+        .assert()
+        .success();
+    let stderr = String::from_utf8_lossy(&assert.get_output().stderr);
+    assert!(stderr.contains("1 converted, 0 skipped, 0 failed"));
+
+    let assert = ign()
+        .args(["--exclude", "*.png"])
+        .arg(temp.path())
+        .arg(out.path())
+        .assert()
+        .success();
+    let stderr = String::from_utf8_lossy(&assert.get_output().stderr);
+    assert!(stderr.contains("0 converted, 0 skipped, 1 filtered, 0 failed"));
+}
+
+#[test]
+fn test_name_template() {
+    let temp = setup_tinycross().unwrap();
+    let out = TempDir::new().unwrap();
+    ign()
+        .args(["--format", "jpeg", "--jpeg-quality", "90"])
+        .args(["--name-template", "{stem}-{palette}.{ext}"])
+        .arg(temp.path())
+        .arg(out.path())
+        .assert()
+        .success();
+    assert!(out.child("tinycross-nord.jpg").path().is_file());
+}
+
+#[test]
+fn test_format_mismatch() {
+    let temp = setup_tinycross().unwrap();
+    ign()
+        .current_dir(temp.path())
+        .args(["--format", "png", "tinycross.png", "nord.jpg"])
+        .assert()
+        .failure();
+    assert!(!temp.child("nord.jpg").path().exists());
+
+    ign()
+        .current_dir(temp.path())
+        .args(["--format", "jpeg", "tinycross.png", "nord.JPEG"])
+        .assert()
+        .success();
+}
+
+#[test]
+fn test_refuses_to_overwrite_input() {
+    let temp = setup_tinycross().unwrap();
+    ign()
+        .current_dir(temp.path())
+        .args(["tinycross.png", "tinycross.png"])
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_refuses_to_overwrite_other_input() {
+    let temp = setup_tinycross().unwrap();
+    // a different image with the name tinycross.png would be converted to
+    ign()
+        .current_dir(temp.path())
+        .args(["--blur", "0.5", "tinycross.png", "tinycross.jpg"])
+        .assert()
+        .success();
+    let jpeg = std::fs::read(temp.child("tinycross.jpg").path()).unwrap();
+
+    let assert = ign()
+        .args(["--format", "jpeg", "--dry-run"])
+        .arg(temp.path())
+        .arg(temp.path())
+        .assert()
+        .success();
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    assert!(stdout.contains("tinycross.jpg (refusing to overwrite an input)"));
+
+    let assert = ign()
+        .args(["--format", "jpeg"])
+        .arg(temp.path())
+        .arg(temp.path())
+        .assert()
+        .success();
+    let stderr = String::from_utf8_lossy(&assert.get_output().stderr);
+    assert!(stderr.contains("refusing to overwrite"));
+    assert!(stderr.contains("0 converted, 0 skipped, 2 failed"));
+    assert_eq!(
+        std::fs::read(temp.child("tinycross.jpg").path()).unwrap(),
+        jpeg
+    );
+}
+
+#[test]
+fn test_stdin_stdout() {
+    let png =
+        std::fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("images/tinycross.png")).unwrap();
+    let assert = ign()
+        .args(["-", "-"])
+        .write_stdin(png.clone())
+        .assert()
+        .success();
+    assert!(assert.get_output().stdout.starts_with(b"\x89PNG"));
+
+    let assert = ign()
+        .args(["--format", "jpeg", "-", "-"])
+        .write_stdin(png)
+        .assert()
+        .success();
+    assert!(assert.get_output().stdout.starts_with(&[0xFF, 0xD8]));
+}
+
+#[test]
+fn test_incremental() {
+    let temp = setup_tinycross().unwrap();
+    let out = TempDir::new().unwrap();
+    let run = || {
+        let assert = ign()
+            .arg("--incremental")
+            .arg(temp.path())
+            .arg(out.path())
+            .assert()
+            .success();
+        String::from_utf8_lossy(&assert.get_output().stderr).into_owned()
+    };
+
+    assert!(run().contains("1 converted, 0 unchanged"));
+    assert!(out.child(".ign-manifest.json").path().is_file());
+    assert!(run().contains("0 converted, 1 unchanged"));
+
+    std::fs::remove_file(temp.child("tinycross.png").path()).unwrap();
+    assert!(run().contains("1 removed"));
+    assert!(!out.child("tinycross.png").path().exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_incremental_unreadable() {
+    let temp = setup_tinycross().unwrap();
+    std::os::unix::fs::symlink("missing.png", temp.child("broken.png").path()).unwrap();
+    let out = TempDir::new().unwrap();
+
+    // the image that can't be read fails without stopping the others
+    let assert = ign()
+        .arg("--incremental")
+        .arg(temp.path())
+        .arg(out.path())
+        .assert()
+        .success();
+    let stderr = String::from_utf8_lossy(&assert.get_output().stderr);
+    assert!(stderr.contains("broken.png"));
+    assert!(stderr.contains("1 converted, 0 unchanged, 0 removed, 0 skipped, 1 failed"));
+    assert!(out.child("tinycross.png").path().is_file());
+}
+
+#[test]
+fn test_watch() {
+    let temp = setup_tinycross().unwrap();
+    let out = TempDir::new().unwrap();
+    let mut watch = std::process::Command::new(assert_cmd::cargo::cargo_bin("ign"))
+        .env("HOME", home())
+        .env("XDG_CONFIG_HOME", home().join(".config"))
+        .arg("--watch")
         .arg(temp.path())
         .arg(out.path())
+        .stderr(std::process::Stdio::null())
+        .spawn()
+        .unwrap();
+
+    // wait for the first conversion before adding another image
+    let wait_for = |path: &Path| {
+        (0..100).any(|_| {
+            std::thread::sleep(std::time::Duration::from_millis(100));
+            path.is_file()
+        })
+    };
+    let converted = wait_for(out.child("tinycross.png").path()) && {
+        std::thread::sleep(std::time::Duration::from_millis(500));
+        temp.child("added.png")
+            .write_file(&Path::new(env!("CARGO_MANIFEST_DIR")).join("images/tinycross.png"))
+            .unwrap();
+        wait_for(out.child("added.png").path())
+    };
+    watch.kill().unwrap();
+    watch.wait().unwrap();
+    assert!(converted);
+}
+
+#[test]
+fn test_gradient() {
+    let temp = setup_tinycross().unwrap();
+    ign()
+        .current_dir(temp.path())
+        .args([
+            "--gradient",
+            "stepped",
+            "tinycross.png",
+            "tinycross-gradient.png",
+        ])
+        .assert()
+        .success();
+    ign()
+        .current_dir(temp.path())
+        .args([
+            "--mode",
+            "nearest",
+            "tinycross.png",
+            "tinycross-nearest.png",
+        ])
+        .assert()
+        .success();
+
+    let gradient = image::open(temp.child("tinycross-gradient.png").path())
+        .unwrap()
+        .to_rgba8();
+    let nearest = image::open(temp.child("tinycross-nearest.png").path())
+        .unwrap()
+        .to_rgba8();
+    assert_eq!(gradient.dimensions(), nearest.dimensions());
+    assert_ne!(gradient, nearest);
+}
+
+#[test]
+fn test_region() {
+    let temp = setup_tinycross().unwrap();
+    ign()
+        .current_dir(temp.path())
+        .args(["--region", "0,0,4,4", "--feather", "2"])
+        .args(["tinycross.png", "tinycross-nord.png"])
+        .assert()
+        .success();
+
+    let original = image::open(temp.child("tinycross.png").path())
+        .unwrap()
+        .to_rgba8();
+    let converted = image::open(temp.child("tinycross-nord.png").path())
+        .unwrap()
+        .to_rgba8();
+    assert_eq!(original.dimensions(), converted.dimensions());
+    for (x, y, pixel) in original.enumerate_pixels() {
+        if x >= 4 || y >= 4 {
+            assert_eq!(pixel, converted.get_pixel(x, y), "pixel {},{}", x, y);
+        }
+    }
+}
+
+#[test]
+fn test_compare() {
+    let temp = setup_tinycross().unwrap();
+    ign()
+        .current_dir(temp.path())
+        .args(["compare", "--layout", "diagonal", "--swatches"])
+        .args(["--labels", "before,after"])
+        .args(["tinycross.png", "tinycross-compare.png"])
+        .assert()
+        .success();
+    assert!(temp.child("tinycross-compare.png").path().is_file());
+}
+
+#[test]
+fn test_report() {
+    let temp = setup_tinycross().unwrap();
+    let assert = ign()
+        .current_dir(temp.path())
+        .args(["--report", "tinycross.png", "tinycross-nord.png"])
+        .assert()
+        .success();
+    assert!(String::from_utf8_lossy(&assert.get_output().stdout).contains("SSIM"));
+}
+
+#[test]
+fn test_stats_json() {
+    let temp = setup_tinycross().unwrap();
+    let assert = ign()
+        .current_dir(temp.path())
+        .args(["stats", "--json", "tinycross.png"])
+        .assert()
+        .success();
+    assert!(String::from_utf8_lossy(&assert.get_output().stdout).contains("\"pixels\""));
+}
+
+#[test]
+fn test_stats_mapping() {
+    let temp = setup_tinycross().unwrap();
+    let assert = ign()
+        .current_dir(temp.path())
+        .args(["--mode", "balanced", "stats", "--mapping", "tinycross.png"])
+        .assert()
+        .success();
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    assert!(stdout.starts_with("CLUSTER"));
+    assert!(stdout.lines().count() > 1);
+
+    // other modes have no mapping table
+    ign()
+        .current_dir(temp.path())
+        .args(["stats", "--mapping", "tinycross.png"])
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_heatmap() {
+    let temp = setup_tinycross().unwrap();
+    ign()
+        .current_dir(temp.path())
+        .args([
+            "--heatmap",
+            "tinycross-heatmap.png",
+            "--colormap",
+            "inferno",
+        ])
+        .args(["tinycross.png", "tinycross-nord.png"])
+        .assert()
+        .success();
+    assert!(temp.child("tinycross-heatmap.png").path().is_file());
+}
+
+#[test]
+fn test_preview_without_output() {
+    let temp = setup_tinycross().unwrap();
+    let assert = ign()
+        .current_dir(temp.path())
+        .args(["--preview", "tinycross.png"])
+        .assert()
+        .success();
+    assert!(String::from_utf8_lossy(&assert.get_output().stdout).contains("\x1b[38;2;"));
+}
+
+#[test]
+fn test_ansi_format() {
+    let temp = setup_tinycross().unwrap();
+    ign()
+        .current_dir(temp.path())
+        .args(["--format", "ansi", "--characters", "shape"])
+        .args(["tinycross.png", "tinycross.ans"])
         .assert()
         .success();
+    let art = std::fs::read_to_string(temp.child("tinycross.ans").path()).unwrap();
+    assert!(art.ends_with("\x1b[0m\n"));
+}
+
+#[test]
+fn test_lut() {
+    let temp = TempDir::new().unwrap();
+    ign()
+        .current_dir(temp.path())
+        .args(["lut", "--size", "9", "nord.cube"])
+        .assert()
+        .success();
+    let cube = std::fs::read_to_string(temp.child("nord.cube").path()).unwrap();
+    assert!(cube.contains("LUT_3D_SIZE 9"));
+
+    ign()
+        .current_dir(temp.path())
+        .args(["lut", "--level", "2", "nord-hald.png"])
+        .assert()
+        .success();
+    assert!(temp.child("nord-hald.png").path().is_file());
+}
+
+#[test]
+fn test_apply_lut() {
+    let temp = setup_tinycross().unwrap();
+    ign()
+        .current_dir(temp.path())
+        .args(["lut", "--size", "5", "nord.cube"])
+        .assert()
+        .success();
+    ign()
+        .current_dir(temp.path())
+        .args(["--lut", "nord.cube", "--lut-interpolation", "trilinear"])
+        .args(["tinycross.png", "tinycross-nord.png"])
+        .assert()
+        .success();
+
+    temp.child("identity.cube")
+        .write_str("LUT_3D_SIZE 2\n0 0 0\n1 0 0\n0 1 0\n1 1 0\n0 0 1\n1 0 1\n0 1 1\n1 1 1\n")
+        .unwrap();
+    ign()
+        .current_dir(temp.path())
+        .args(["--lut", "identity.cube", "--lut-only"])
+        .args(["tinycross.png", "tinycross-identity.png"])
+        .assert()
+        .success();
+    let original = image::open(temp.child("tinycross.png").path())
+        .unwrap()
+        .to_rgba8();
+    let identity = image::open(temp.child("tinycross-identity.png").path())
+        .unwrap()
+        .to_rgba8();
+    assert_eq!(original, identity);
+}
+
+#[test]
+fn test_presets() {
+    let temp = setup_tinycross().unwrap();
+    ign()
+        .current_dir(temp.path())
+        .args(["--preset", "photo", "--quantize", "0"])
+        .args(["tinycross.png", "tinycross-photo.png"])
+        .assert()
+        .success();
+
+    temp.child("presets.toml")
+        .write_str("[soft]\nmode = \"luminance\"\nblur = 0.3\n")
+        .unwrap();
+    ign()
+        .current_dir(temp.path())
+        .args(["--presets", "presets.toml", "--preset", "soft"])
+        .args(["tinycross.png", "tinycross-soft.png"])
+        .assert()
+        .success();
+
+    // flags override the values of the preset
+    ign()
+        .current_dir(temp.path())
+        .args(["--presets", "presets.toml", "--preset", "soft"])
+        .args(["--mode", "nearest", "--blur", "0"])
+        .args(["tinycross.png", "tinycross-overridden.png"])
+        .assert()
+        .success();
+    ign()
+        .current_dir(temp.path())
+        .args(["--mode", "nearest", "--blur", "0"])
+        .args(["tinycross.png", "tinycross-nearest.png"])
+        .assert()
+        .success();
+    let open = |name: &str| image::open(temp.child(name).path()).unwrap().to_rgba8();
+    assert_ne!(open("tinycross-soft.png"), open("tinycross-nearest.png"));
+    assert_eq!(
+        open("tinycross-overridden.png"),
+        open("tinycross-nearest.png")
+    );
+
+    ign()
+        .current_dir(temp.path())
+        .args([
+            "--preset",
+            "portrait",
+            "tinycross.png",
+            "tinycross-nord.png",
+        ])
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_config() {
+    let temp = setup_tinycross().unwrap();
+    temp.child(".ign.toml")
+        .write_str("mode = \"luminance\"\npalette = \"frost\"\n")
+        .unwrap();
+
+    let assert = ign()
+        .current_dir(temp.path())
+        .args(["--blur", "0.5", "config", "show"])
+        .assert()
+        .success();
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    assert!(stdout.contains("mode = \"luminance\""));
+    assert!(stdout.contains("palette = \"frost\""));
+    assert!(stdout.contains("blur = 0.5"));
+
+    ign()
+        .current_dir(temp.path())
+        .args(["tinycross.png", "tinycross-frost.png"])
+        .assert()
+        .success();
+
+    temp.child("broken.toml").write_str("colour = 1\n").unwrap();
+    ign()
+        .current_dir(temp.path())
+        .args(["--config", "broken.toml", "tinycross.png", "out.png"])
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_config_layers() {
+    let temp = setup_tinycross().unwrap();
+    temp.child(".ign.toml")
+        .write_str("preset = \"photo\"\nblur = 0.2\nmode = \"luminance\"\n")
+        .unwrap();
+    let show = |args: &[&str]| {
+        let assert = ign()
+            .current_dir(temp.path())
+            .args(args)
+            .args(["config", "show"])
+            .assert()
+            .success();
+        String::from_utf8_lossy(&assert.get_output().stdout).into_owned()
+    };
+
+    // config values override the preset of the config file
+    let stdout = show(&[]);
+    assert!(stdout.contains("blur = 0.2"));
+    assert!(stdout.contains("quantize = 10"));
+    // a preset on the command line overrides config values
+    let stdout = show(&["--preset", "pixel-art"]);
+    assert!(stdout.contains("mode = \"nearest\""));
+    assert!(stdout.contains("no-dither = true"));
+    assert!(stdout.contains("blur = 0.0"));
+    // and options on the command line override that preset
+    let stdout = show(&["--preset", "pixel-art", "--mode", "hue"]);
+    assert!(stdout.contains("mode = \"hue\""));
+    assert!(stdout.contains("no-dither = true"));
 }